@@ -25,13 +25,28 @@ pub enum Error {
 	TooManyAttempts,
 	#[error("invalid email address")]
 	InvalidEmail,
+	/// the password was correct, but the login has to be completed with a two-factor code
+	#[error("two-factor authentication required")]
+	TwoFactorRequired(TwoFactorLogin),
 }
 
 impl Auth {
 	pub async fn login(&self, email: String, password: String) -> Result<AuthToken, Error> {
-		make_request(&self.client, &auth::LoginRequest { email, password })
-			.await
-			.map(|success| AuthToken(success.auth_token))
+		let request = auth::LoginRequest {
+			email,
+			password,
+			client: Some(client_info()),
+		};
+
+		match make_request(&self.client, &request).await? {
+			auth::LoginResponse::Success(success) => Ok(AuthToken(success.auth_token)),
+			auth::LoginResponse::TwoFactorRequired(challenge) => {
+				Err(Error::TwoFactorRequired(TwoFactorLogin {
+					client: Arc::clone(&self.client),
+					challenge: challenge.challenge,
+				}))
+			}
+		}
 	}
 	pub async fn register(&self, email: String) -> Result<EmailVerifier, Error> {
 		if !EmailAddress::is_valid(&email) {
@@ -49,6 +64,26 @@ impl Auth {
 	}
 }
 
+#[derive(Debug, Clone)]
+pub struct TwoFactorLogin {
+	client: Arc<InnerClient>,
+	challenge: Uuid,
+}
+
+impl TwoFactorLogin {
+	/// `code` is either from the authenticator or one of the recovery codes
+	pub async fn complete(&self, code: String) -> Result<AuthToken, Error> {
+		let request = auth::TwoFactorLoginRequest {
+			challenge: self.challenge,
+			code,
+		};
+
+		make_request(&self.client, &request)
+			.await
+			.map(|success| AuthToken(success.auth_token))
+	}
+}
+
 #[derive(Debug, Clone)]
 pub struct EmailVerifier {
 	client: Arc<InnerClient>,
@@ -104,6 +139,7 @@ impl CreateAccount {
 				registration_id: self.registration_id,
 				username,
 				password,
+				client: Some(client_info()),
 			},
 		)
 		.await?;
@@ -112,6 +148,13 @@ impl CreateAccount {
 	}
 }
 
+fn client_info() -> auth::ClientInfo {
+	auth::ClientInfo {
+		name: "salix".to_owned(),
+		version: env!("CARGO_PKG_VERSION").to_owned(),
+	}
+}
+
 async fn make_request<R: Request>(client: &InnerClient, req: &R) -> Result<R::Response, Error> {
 	let api_url = client
 		.config
@@ -1,6 +1,7 @@
 use serde::{Serialize, de::DeserializeOwned};
 
 pub mod v1;
+pub mod v2;
 
 pub trait Request: Serialize + DeserializeOwned {
 	/// JSON payload if the request was successful (code 200)
@@ -8,7 +8,7 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use uuid::Uuid;
 
-/// All possible errors that can be returned in `/auth/v1` as JSON
+/// All possible errors that can be returned in `/auth/v1` and `/auth/v2` as JSON
 #[derive(Serialize, Deserialize, Debug, Error)]
 #[serde(tag = "code")]
 pub enum Error {
@@ -24,24 +24,67 @@ pub enum Error {
 	/// the email being valid, or the username being long enough, etc
 	#[error("invalid request")]
 	InvalidRequest,
+	#[error("too many requests, retry after {retry_after}s")]
+	RateLimited {
+		/// seconds until the request can be retried
+		retry_after: u32,
+	},
+}
+
+/// Identifies the client software of a session, shown in [`SessionInfo`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClientInfo {
+	pub name: String,
+	pub version: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct LoginRequest {
 	pub email: String,
 	pub password: String,
+	#[serde(default)]
+	pub client: Option<ClientInfo>,
 }
 #[derive(Serialize, Deserialize, Debug)]
 pub struct LoginSuccess {
 	pub auth_token: Uuid,
 }
+/// The password was correct, but the account has two-factor authentication enabled.
+/// The login has to be completed with [`TwoFactorLoginRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TwoFactorChallenge {
+	pub challenge: Uuid,
+}
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum LoginResponse {
+	Success(LoginSuccess),
+	TwoFactorRequired(TwoFactorChallenge),
+}
 impl Request for LoginRequest {
-	type Response = LoginSuccess;
+	type Response = LoginResponse;
 	type Error = Error;
 
 	const PATH: &'static str = "/login";
 }
 
+/// Second step of logging in with two-factor authentication enabled.
+///
+/// Fails with [`Error::Unauthorized`] if the challenge expired or had too many attempts,
+/// in which case the login has to be started again
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TwoFactorLoginRequest {
+	pub challenge: Uuid,
+	/// Either a code from the authenticator or one of the recovery codes
+	pub code: String,
+}
+impl Request for TwoFactorLoginRequest {
+	type Response = LoginSuccess;
+	type Error = Error;
+
+	const PATH: &'static str = "/login_two_factor";
+}
+
 /// First stage when creating a new account
 #[derive(Serialize, Deserialize, Debug)]
 pub struct StartEmailVerifyRequest {
@@ -78,6 +121,8 @@ pub struct FinalizeNewAccountRequest {
 	pub registration_id: Uuid,
 	pub username: String,
 	pub password: String,
+	#[serde(default)]
+	pub client: Option<ClientInfo>,
 }
 impl Request for FinalizeNewAccountRequest {
 	type Response = LoginSuccess;
@@ -85,3 +130,255 @@ impl Request for FinalizeNewAccountRequest {
 
 	const PATH: &'static str = "/finalize_new_account";
 }
+
+/// Sends an email with a password reset token, if an account with this email exists.
+///
+/// Always succeeds for valid emails, so that it doesn't reveal whether the email is registered
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RequestPasswordReset {
+	pub email: String,
+}
+impl Request for RequestPasswordReset {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/request_password_reset";
+}
+
+/// Sets a new password with the token from the reset email.
+/// All existing sessions of the user are logged out.
+///
+/// If the account has two-factor authentication enabled, the password is only changed
+/// once the login is completed with [`TwoFactorLoginRequest`]. The token can't be used again
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CompletePasswordReset {
+	pub token: String,
+	pub new_password: String,
+	#[serde(default)]
+	pub client: Option<ClientInfo>,
+}
+impl Request for CompletePasswordReset {
+	type Response = LoginResponse;
+	type Error = Error;
+
+	const PATH: &'static str = "/complete_password_reset";
+}
+
+/// Logs out the session of the token
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LogoutRequest {
+	pub auth_token: Uuid,
+}
+impl Request for LogoutRequest {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/logout";
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionInfo {
+	/// Public id of the session, used to revoke it
+	pub id: Uuid,
+	/// unix timestamp in milliseconds
+	pub created_at: i64,
+	/// unix timestamp in milliseconds
+	pub last_used_at: i64,
+	/// `None` if the client didn't report it when logging in
+	pub client: Option<ClientInfo>,
+	/// The IP address the session was last used from
+	pub ip: Option<String>,
+	/// Whether this is the session of the token used for the request
+	pub current: bool,
+}
+
+/// Lists all active sessions of the user
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ListSessionsRequest {
+	pub auth_token: Uuid,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionList {
+	/// The most recently used first
+	pub sessions: Vec<SessionInfo>,
+}
+impl Request for ListSessionsRequest {
+	type Response = SessionList;
+	type Error = Error;
+
+	const PATH: &'static str = "/sessions";
+}
+
+/// Logs out a session of the user. Connections using that session are closed
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RevokeSessionRequest {
+	pub auth_token: Uuid,
+	pub session_id: Uuid,
+}
+impl Request for RevokeSessionRequest {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/revoke_session";
+}
+
+/// Logs out all sessions of the user except for the one of the token
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RevokeOtherSessionsRequest {
+	pub auth_token: Uuid,
+}
+impl Request for RevokeOtherSessionsRequest {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/revoke_other_sessions";
+}
+
+/// Changes the password of the user
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChangePasswordRequest {
+	pub auth_token: Uuid,
+	/// Fails with [`Error::Unauthorized`] if this is incorrect
+	pub current_password: String,
+	pub new_password: String,
+	/// Also log out all sessions except for the one of the token
+	pub revoke_other_sessions: bool,
+}
+impl Request for ChangePasswordRequest {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/change_password";
+}
+
+/// Sends a verification code to the new email, which is then used in
+/// [`CompleteEmailChangeRequest`] to change the email of the user.
+///
+/// Succeeds even if the new email is already registered, without sending a code
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StartEmailChangeRequest {
+	pub auth_token: Uuid,
+	/// Fails with [`Error::Unauthorized`] if this is incorrect
+	pub current_password: String,
+	pub new_email: String,
+}
+impl Request for StartEmailChangeRequest {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/start_email_change";
+}
+
+/// Changes the email of the user and notifies the old email about it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CompleteEmailChangeRequest {
+	pub auth_token: Uuid,
+	pub new_email: String,
+	pub code: u32,
+}
+impl Request for CompleteEmailChangeRequest {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/complete_email_change";
+}
+
+/// Schedules the account of the user to be deleted after a grace period, and logs out all sessions.
+/// Logging in again before then cancels the deletion.
+///
+/// Messages of deleted accounts stay in their chatrooms without an author
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteAccountRequest {
+	pub auth_token: Uuid,
+	/// Fails with [`Error::Unauthorized`] if this is incorrect
+	pub password: String,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AccountDeletionScheduled {
+	/// unix timestamp in milliseconds
+	pub deletion_at: i64,
+}
+impl Request for DeleteAccountRequest {
+	type Response = AccountDeletionScheduled;
+	type Error = Error;
+
+	const PATH: &'static str = "/delete_account";
+}
+
+/// Starts enabling TOTP two-factor authentication, which has to be confirmed
+/// with [`ConfirmTotpEnrollmentRequest`] before it is required when logging in.
+///
+/// Fails with [`Error::InvalidRequest`] if it is already enabled, or the server doesn't support it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StartTotpEnrollmentRequest {
+	pub auth_token: Uuid,
+	/// Fails with [`Error::Unauthorized`] if this is incorrect
+	pub password: String,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TotpEnrollment {
+	/// base32 encoded, for entering into an authenticator manually
+	pub secret: String,
+	/// `otpauth://` URI, usually shown as a QR code
+	pub provisioning_uri: String,
+}
+impl Request for StartTotpEnrollmentRequest {
+	type Response = TotpEnrollment;
+	type Error = Error;
+
+	const PATH: &'static str = "/start_totp_enrollment";
+}
+
+/// Enables two-factor authentication with a code from the authenticator
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConfirmTotpEnrollmentRequest {
+	pub auth_token: Uuid,
+	/// Fails with [`Error::IncorrectCode`] if this is incorrect
+	pub code: String,
+}
+/// Single use codes that can be used instead of the authenticator.
+/// They are shown to the user only once
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecoveryCodes {
+	pub codes: Vec<String>,
+}
+impl Request for ConfirmTotpEnrollmentRequest {
+	type Response = RecoveryCodes;
+	type Error = Error;
+
+	const PATH: &'static str = "/confirm_totp_enrollment";
+}
+
+/// Disables two-factor authentication
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DisableTotpRequest {
+	pub auth_token: Uuid,
+	/// Fails with [`Error::Unauthorized`] if this is incorrect
+	pub password: String,
+	/// Either a code from the authenticator or one of the recovery codes.
+	/// Fails with [`Error::IncorrectCode`] if this is incorrect
+	pub code: String,
+}
+impl Request for DisableTotpRequest {
+	type Response = ();
+	type Error = Error;
+
+	const PATH: &'static str = "/disable_totp";
+}
+
+/// Replaces all recovery codes with new ones
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RegenerateRecoveryCodesRequest {
+	pub auth_token: Uuid,
+	/// Fails with [`Error::Unauthorized`] if this is incorrect
+	pub password: String,
+	/// Either a code from the authenticator or one of the recovery codes.
+	/// Fails with [`Error::IncorrectCode`] if this is incorrect
+	pub code: String,
+}
+impl Request for RegenerateRecoveryCodesRequest {
+	type Response = RecoveryCodes;
+	type Error = Error;
+
+	const PATH: &'static str = "/regenerate_recovery_codes";
+}
@@ -0,0 +1,87 @@
+//! v2 of the auth API
+//!
+//! Instead of a single long-lived auth token, logging in returns a short-lived access token
+//! together with a refresh token. The access token is used everywhere an auth token is expected,
+//! including the v1 session endpoints and [`Authenticate`][crate::c2s::Authenticate].
+//!
+//! Each refresh token can be used only once, refreshing returns a new one.
+//! Using an already used refresh token again revokes the whole session, as it must have been stolen.
+//!
+//! Email verification before creating an account and managing the account is still done with v1.
+
+use super::Request;
+pub use super::v1::{ClientInfo, Error, TwoFactorChallenge};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Tokens {
+	pub access_token: Uuid,
+	/// Seconds until the access token expires
+	pub access_token_expires_in: u32,
+	pub refresh_token: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoginRequest {
+	pub email: String,
+	pub password: String,
+	#[serde(default)]
+	pub client: Option<ClientInfo>,
+}
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum LoginResponse {
+	Success(Tokens),
+	TwoFactorRequired(TwoFactorChallenge),
+}
+impl Request for LoginRequest {
+	type Response = LoginResponse;
+	type Error = Error;
+
+	const PATH: &'static str = "/login";
+}
+
+/// Last stage when creating a new account, after [`VerifyEmailRequest`][super::v1::VerifyEmailRequest]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FinalizeNewAccountRequest {
+	pub registration_id: Uuid,
+	pub username: String,
+	pub password: String,
+	#[serde(default)]
+	pub client: Option<ClientInfo>,
+}
+impl Request for FinalizeNewAccountRequest {
+	type Response = Tokens;
+	type Error = Error;
+
+	const PATH: &'static str = "/finalize_new_account";
+}
+
+/// Exchanges a refresh token for new tokens.
+/// Fails with [`Error::Unauthorized`] if the session expired or was revoked
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RefreshRequest {
+	pub refresh_token: Uuid,
+}
+impl Request for RefreshRequest {
+	type Response = Tokens;
+	type Error = Error;
+
+	const PATH: &'static str = "/refresh";
+}
+
+/// Second step of logging in with two-factor authentication enabled,
+/// same as [`TwoFactorLoginRequest`][super::v1::TwoFactorLoginRequest] in v1
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TwoFactorLoginRequest {
+	pub challenge: Uuid,
+	/// Either a code from the authenticator or one of the recovery codes
+	pub code: String,
+}
+impl Request for TwoFactorLoginRequest {
+	type Response = Tokens;
+	type Error = Error;
+
+	const PATH: &'static str = "/login_two_factor";
+}
@@ -1,15 +1,279 @@
-use crate::C2S;
 use crate::r#macro::message;
+use crate::s2c::ChatroomRole;
+use crate::{C2S, Capabilities};
 use bitcode::{Decode, Encode};
 
-/// First packet that the client must send to the server
+/// Opens the version negotiation handshake, must be sent before [`Authenticate`]
+///
+/// The format of this packet will never change between protocol versions.
+#[derive(Encode, Decode, Debug)]
+pub struct Hello {
+	/// The oldest protocol version that the client supports
+	pub min_version: u32,
+	/// The newest protocol version that the client supports
+	pub max_version: u32,
+	/// Optional features that the client supports
+	pub capabilities: Capabilities,
+}
+message!(Hello => C2S);
+
+/// First packet that the client must send to the server (after the [`Hello`] handshake, if negotiating)
 #[derive(Encode, Decode, Debug)]
 pub struct Authenticate {
 	pub auth_token: [u8; 16],
 }
 message!(Authenticate => C2S);
 
+/// Starts receiving new messages in a chatroom
+#[derive(Encode, Decode, Debug)]
+pub struct JoinChat {
+	pub chatroom: [u8; 16],
+	/// Sequence id of the last message the client has seen in this chatroom.
+	///
+	/// When resuming after a reconnect, all messages after this one will be sent first,
+	/// before continuing with new ones. If too many messages were missed, only the latest
+	/// ones are sent and the rest can be fetched with [`FetchHistory`].
+	pub last_seen_seq_id: Option<i64>,
+	/// [`change_id`][crate::s2c::MessageChanged::change_id] of the last edit, deletion or reaction change
+	/// the client has seen in this chatroom.
+	///
+	/// When resuming, the messages changed after it are sent as [`MessageChanged`][crate::s2c::MessageChanged]
+	/// first, up to the same limit as new messages.
+	pub last_seen_change_id: Option<i64>,
+}
+
+/// Stops receiving new messages in a chatroom
+#[derive(Encode, Decode, Debug)]
+pub struct LeaveChat {
+	pub chatroom: [u8; 16],
+}
+
+/// Answered with [`MessageAck`][crate::s2c::MessageAck] once the message is stored,
+/// or with [`MessageRejected`][crate::s2c::MessageRejected]
 #[derive(Encode, Decode, Debug)]
 pub struct SendMessage {
+	pub chatroom: [u8; 16],
+	/// Client generated unique id of the message.
+	///
+	/// Sending a message with a nonce already used in the same chatroom will not create a new message,
+	/// so it is safe to retry sending if the acknowledgement was not received.
+	pub nonce: [u8; 16],
 	pub message: String,
+	/// Message in the same chatroom that this one replies to
+	pub reply_to: Option<[u8; 16]>,
+}
+
+/// Requests older messages of a chatroom. Answered with [`History`][crate::s2c::History]
+#[derive(Encode, Decode, Debug)]
+pub struct FetchHistory {
+	pub chatroom: [u8; 16],
+	/// Only messages with a lower sequence id will be returned.
+	/// `None` to fetch the latest messages
+	pub before_seq_id: Option<i64>,
+	/// Max number of messages to return. The server may return less
+	pub limit: u32,
+}
+
+/// Replaces the content of a message. Only the author can edit their messages
+#[derive(Encode, Decode, Debug)]
+pub struct EditMessage {
+	pub message_id: [u8; 16],
+	pub message: String,
+}
+
+/// Deletes a message, leaving a tombstone in its place. Only the author can delete their messages
+#[derive(Encode, Decode, Debug)]
+pub struct DeleteMessage {
+	pub message_id: [u8; 16],
+}
+
+/// Tells the other users in a joined chatroom that the user is typing
+///
+/// While typing, this needs to be resent every few seconds, otherwise the typing expires
+/// on the server after a couple of seconds. Rejected with [`InvalidPacket`][crate::s2c::Error::InvalidPacket]
+/// without [`Capabilities::TYPING`].
+#[derive(Encode, Decode, Debug)]
+pub struct Typing {
+	pub chatroom: [u8; 16],
+	/// `false` to stop typing immediately
+	pub typing: bool,
+}
+
+/// Marks this connection as away (or back), for example when the user is idle.
+///
+/// The user is shown as online as long as any of their connections is not away.
+#[derive(Encode, Decode, Debug)]
+pub struct SetAway {
+	pub away: bool,
+}
+
+/// Requests the current presence of users. Answered with [`PresenceInfo`][crate::s2c::PresenceInfo]
+#[derive(Encode, Decode, Debug)]
+pub struct QueryPresence {
+	/// Max 100 users, the rest is ignored
+	pub users: Vec<[u8; 16]>,
+}
+
+/// Marks all messages up to and including `sequence_id` as read.
+///
+/// Read markers only move forward, marking an older message does nothing.
+#[derive(Encode, Decode, Debug)]
+pub struct MarkRead {
+	pub chatroom: [u8; 16],
+	pub sequence_id: i64,
+}
+
+/// Requests unread counts of all chatrooms the user is a member of.
+/// Answered with [`UnreadCounts`][crate::s2c::UnreadCounts]
+#[derive(Encode, Decode, Debug)]
+pub struct FetchUnreadCounts;
+
+/// Reacts to a message with an emoji. Reacting twice with the same emoji does nothing
+#[derive(Encode, Decode, Debug)]
+pub struct AddReaction {
+	pub message_id: [u8; 16],
+	/// A single emoji or emoji sequence, such as a flag. Max 64 bytes
+	pub emoji: String,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct RemoveReaction {
+	pub message_id: [u8; 16],
+	pub emoji: String,
+}
+
+/// Requests replies in a thread. Answered with [`Thread`][crate::s2c::Thread],
+/// or rejected with [`MessageNotFound`][crate::s2c::Error::MessageNotFound] if the
+/// root doesn't exist or is in a chatroom the user isn't a member of
+#[derive(Encode, Decode, Debug)]
+pub struct FetchThread {
+	/// The first message of the thread, which all the replies lead to
+	pub thread_root: [u8; 16],
+	/// Only messages with a lower sequence id will be returned.
+	/// `None` to fetch the latest replies
+	pub before_seq_id: Option<i64>,
+	/// Max number of messages to return. The server may return less
+	pub limit: u32,
+}
+
+/// Opens the direct chat with a user, creating it if needed.
+/// Answered with [`DirectChatOpened`][crate::s2c::DirectChatOpened]
+///
+/// The chat is a normal chatroom with both users as members, so it still has to be joined with [`JoinChat`].
+#[derive(Encode, Decode, Debug)]
+pub struct OpenDirectChat {
+	pub username: String,
+}
+
+/// Requests all chatrooms the user is a member of. Answered with [`Chatrooms`][crate::s2c::Chatrooms]
+#[derive(Encode, Decode, Debug)]
+pub struct ListChatrooms;
+
+/// Creates a new chatroom with the user as its owner and only member.
+/// Answered with [`ChatroomCreated`][crate::s2c::ChatroomCreated]
+#[derive(Encode, Decode, Debug)]
+pub struct CreateChatroom {
+	/// 1 to 255 characters
+	pub name: String,
+}
+
+/// Only the owner can rename the chatroom
+#[derive(Encode, Decode, Debug)]
+pub struct RenameChatroom {
+	pub chatroom: [u8; 16],
+	/// 1 to 255 characters
+	pub name: String,
+}
+
+/// Archived chatrooms are read only. Only the owner can archive the chatroom
+#[derive(Encode, Decode, Debug)]
+pub struct SetChatroomArchived {
+	pub chatroom: [u8; 16],
+	pub archived: bool,
+}
+
+/// Deletes the chatroom with all of its messages. Only the owner can delete the chatroom
+#[derive(Encode, Decode, Debug)]
+pub struct DeleteChatroom {
+	pub chatroom: [u8; 16],
+}
+
+/// Requests the members of a chatroom. Answered with [`ChatroomMembers`][crate::s2c::ChatroomMembers]
+#[derive(Encode, Decode, Debug)]
+pub struct FetchChatroomMembers {
+	pub chatroom: [u8; 16],
+}
+
+/// Only the owner can change roles.
+///
+/// Making another member the owner transfers the ownership, the previous owner becomes a moderator.
+#[derive(Encode, Decode, Debug)]
+pub struct SetMemberRole {
+	pub chatroom: [u8; 16],
+	pub user_id: [u8; 16],
+	pub role: ChatroomRole,
+}
+
+/// Removes a member from the chatroom, they can still be added again.
+///
+/// Moderation actions can only be taken against members with a lower role.
+#[derive(Encode, Decode, Debug)]
+pub struct KickMember {
+	pub chatroom: [u8; 16],
+	pub user_id: [u8; 16],
+}
+
+/// Removes a member from the chatroom and prevents them from being added again.
+/// The user doesn't have to be a member
+#[derive(Encode, Decode, Debug)]
+pub struct BanMember {
+	pub chatroom: [u8; 16],
+	pub user_id: [u8; 16],
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct UnbanMember {
+	pub chatroom: [u8; 16],
+	pub user_id: [u8; 16],
+}
+
+/// Prevents a member from sending, editing or deleting messages and reacting for a while.
+/// Muting an already muted member replaces the mute
+#[derive(Encode, Decode, Debug)]
+pub struct MuteMember {
+	pub chatroom: [u8; 16],
+	pub user_id: [u8; 16],
+	/// At most 1 year
+	pub duration_secs: u32,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct UnmuteMember {
+	pub chatroom: [u8; 16],
+	pub user_id: [u8; 16],
+}
+
+/// Creates an invite code that anyone can redeem with [`RedeemInvite`] to become a member.
+/// Only moderators and the owner can create invites.
+/// Answered with [`InviteCreated`][crate::s2c::InviteCreated]
+#[derive(Encode, Decode, Debug)]
+pub struct CreateInvite {
+	pub chatroom: [u8; 16],
+	/// `None` never expires. At most 30 days
+	pub expires_in_secs: Option<u32>,
+	/// `None` for unlimited uses
+	pub max_uses: Option<u32>,
+}
+
+/// Only moderators and the owner can revoke invites
+#[derive(Encode, Decode, Debug)]
+pub struct RevokeInvite {
+	pub code: String,
+}
+
+/// Becomes a member of the chatroom of the invite.
+/// Answered with [`InviteRedeemed`][crate::s2c::InviteRedeemed]
+#[derive(Encode, Decode, Debug)]
+pub struct RedeemInvite {
+	pub code: String,
 }
@@ -0,0 +1,54 @@
+use bitcode::{Decode, Encode};
+use std::ops::{BitAnd, BitOr};
+
+/// A set of optional protocol features
+///
+/// The client sends the capabilities it supports in [`Hello`][crate::c2s::Hello]
+/// and the server responds with the ones that will be used for the connection.
+/// Unknown capabilities are simply ignored, so new ones can be added without bumping [`VERSION`][crate::VERSION].
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Capabilities(pub u64);
+
+impl Capabilities {
+	pub const NONE: Self = Self(0);
+	/// Receiving [`MessageEdited`][crate::s2c::MessageEdited], [`MessageDeleted`][crate::s2c::MessageDeleted]
+	/// and [`MessageChanged`][crate::s2c::MessageChanged]
+	pub const MESSAGE_EDITS: Self = Self(1 << 0);
+	/// Sending [`Typing`][crate::c2s::Typing] and receiving [`TypingUpdate`][crate::s2c::TypingUpdate]
+	pub const TYPING: Self = Self(1 << 1);
+	/// Receiving [`PresenceChanged`][crate::s2c::PresenceChanged]
+	pub const PRESENCE: Self = Self(1 << 2);
+	/// Receiving [`ReadMarkerUpdated`][crate::s2c::ReadMarkerUpdated]
+	pub const READ_MARKERS: Self = Self(1 << 3);
+	/// Receiving [`ReactionUpdated`][crate::s2c::ReactionUpdated] and [`MessageChanged`][crate::s2c::MessageChanged]
+	pub const REACTIONS: Self = Self(1 << 4);
+	/// Receiving [`ChatroomListChanged`][crate::s2c::ChatroomListChanged]
+	pub const CHATROOM_UPDATES: Self = Self(1 << 5);
+	/// Receiving [`MembershipUpdated`][crate::s2c::MembershipUpdated]
+	pub const MODERATION: Self = Self(1 << 6);
+
+	pub const fn contains(self, other: Self) -> bool {
+		self.0 & other.0 == other.0
+	}
+	pub const fn union(self, other: Self) -> Self {
+		Self(self.0 | other.0)
+	}
+	pub const fn intersection(self, other: Self) -> Self {
+		Self(self.0 & other.0)
+	}
+}
+
+impl BitOr for Capabilities {
+	type Output = Self;
+
+	fn bitor(self, rhs: Self) -> Self::Output {
+		self.union(rhs)
+	}
+}
+impl BitAnd for Capabilities {
+	type Output = Self;
+
+	fn bitand(self, rhs: Self) -> Self::Output {
+		self.intersection(rhs)
+	}
+}
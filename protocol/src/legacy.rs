@@ -0,0 +1,7 @@
+//! Encodings of older protocol versions that are still supported
+//!
+//! Only the packets whose encoding changed since that version are redefined,
+//! the rest are shared with the newest version.
+
+pub mod v6;
+pub mod v8;
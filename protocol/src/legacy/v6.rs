@@ -0,0 +1,126 @@
+//! v6 of the websocket protocol
+//!
+//! Packets that didn't exist in v6 are never sent to v6 clients.
+
+use super::v8::JoinChat;
+use crate::c2s::{FetchHistory, LeaveChat};
+use crate::r#macro::message;
+use crate::s2c::MessageAck;
+use crate::{c2s, s2c};
+use bitcode::{Decode, Encode};
+
+message!(C2S => crate::C2S);
+#[derive(Encode, Decode, Debug)]
+pub enum C2S {
+	JoinChat(JoinChat),
+	LeaveChat(LeaveChat),
+	SendMessage(SendMessage),
+	FetchHistory(FetchHistory),
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct SendMessage {
+	pub chatroom: [u8; 16],
+	pub nonce: [u8; 16],
+	pub message: String,
+}
+
+impl From<C2S> for crate::C2S {
+	fn from(value: C2S) -> Self {
+		match value {
+			C2S::JoinChat(x) => Self::JoinChat(x.into()),
+			C2S::LeaveChat(x) => Self::LeaveChat(x),
+			C2S::SendMessage(x) => Self::SendMessage(c2s::SendMessage {
+				chatroom: x.chatroom,
+				nonce: x.nonce,
+				message: x.message,
+				reply_to: None,
+			}),
+			C2S::FetchHistory(x) => Self::FetchHistory(x),
+		}
+	}
+}
+
+message!(S2C => crate::S2C);
+#[derive(Encode, Decode, Debug)]
+pub enum S2C {
+	Error(Error),
+	NewMessage(ChatMessage),
+	MessageAck(MessageAck),
+	History(History),
+}
+
+#[derive(Encode, Decode, Debug)]
+pub enum Error {
+	InvalidPacket,
+	Internal,
+	Unauthorized,
+	TimedOut,
+	TextFrame,
+	NotChatroomMember,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct ChatMessage {
+	pub id: [u8; 16],
+	pub chatroom: [u8; 16],
+	pub sequence_id: i64,
+	pub user_id: [u8; 16],
+	pub username: String,
+	pub message: String,
+	pub sent_at: i64,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct History {
+	pub chatroom: [u8; 16],
+	pub messages: Vec<ChatMessage>,
+}
+
+impl S2C {
+	/// Returns `None` if the packet didn't exist in v6
+	pub fn from_current(packet: crate::S2C) -> Option<Self> {
+		Some(match packet {
+			crate::S2C::Error(x) => Self::Error(x.into()),
+			crate::S2C::NewMessage(x) => Self::NewMessage(x.into()),
+			crate::S2C::MessageAck(x) => Self::MessageAck(x),
+			crate::S2C::History(x) => Self::History(History {
+				chatroom: x.chatroom,
+				messages: x.messages.into_iter().map(ChatMessage::from).collect(),
+			}),
+			_ => return None,
+		})
+	}
+}
+
+// errors that didn't exist in v6 are reported as the closest one
+impl From<s2c::Error> for Error {
+	fn from(value: s2c::Error) -> Self {
+		match value {
+			s2c::Error::InvalidPacket => Self::InvalidPacket,
+			s2c::Error::Unauthorized => Self::Unauthorized,
+			s2c::Error::TimedOut => Self::TimedOut,
+			s2c::Error::TextFrame => Self::TextFrame,
+			s2c::Error::NotChatroomMember
+			| s2c::Error::ChatroomArchived
+			| s2c::Error::Muted
+			| s2c::Error::NotPermitted
+			| s2c::Error::BannedFromChatroom => Self::NotChatroomMember,
+			_ => Self::Internal,
+		}
+	}
+}
+
+impl From<s2c::ChatMessage> for ChatMessage {
+	fn from(value: s2c::ChatMessage) -> Self {
+		Self {
+			id: value.id,
+			chatroom: value.chatroom,
+			sequence_id: value.sequence_id,
+			user_id: value.user_id,
+			username: value.username,
+			message: value.message,
+			sent_at: value.sent_at,
+		}
+	}
+}
@@ -0,0 +1,202 @@
+//! v8 of the websocket protocol, also used by v7 clients
+//!
+//! v7 only differs in errors, which are converted before getting here.
+//! Packets that didn't exist in v8 are never sent to v8 clients.
+
+use crate::c2s::*;
+use crate::r#macro::message;
+use crate::s2c::*;
+use bitcode::{Decode, Encode};
+
+message!(C2S => crate::C2S);
+#[derive(Encode, Decode, Debug)]
+pub enum C2S {
+	JoinChat(JoinChat),
+	LeaveChat(LeaveChat),
+	SendMessage(SendMessage),
+	FetchHistory(FetchHistory),
+	EditMessage(EditMessage),
+	DeleteMessage(DeleteMessage),
+	Typing(Typing),
+	SetAway(SetAway),
+	QueryPresence(QueryPresence),
+	MarkRead(MarkRead),
+	FetchUnreadCounts(FetchUnreadCounts),
+	AddReaction(AddReaction),
+	RemoveReaction(RemoveReaction),
+	FetchThread(FetchThread),
+	OpenDirectChat(OpenDirectChat),
+	ListChatrooms(ListChatrooms),
+	CreateChatroom(CreateChatroom),
+	RenameChatroom(RenameChatroom),
+	SetChatroomArchived(SetChatroomArchived),
+	DeleteChatroom(DeleteChatroom),
+	FetchChatroomMembers(FetchChatroomMembers),
+	SetMemberRole(SetMemberRole),
+	KickMember(KickMember),
+	BanMember(BanMember),
+	UnbanMember(UnbanMember),
+	MuteMember(MuteMember),
+	UnmuteMember(UnmuteMember),
+	CreateInvite(CreateInvite),
+	RevokeInvite(RevokeInvite),
+	RedeemInvite(RedeemInvite),
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct JoinChat {
+	pub chatroom: [u8; 16],
+	pub last_seen_seq_id: Option<i64>,
+}
+
+impl From<JoinChat> for crate::c2s::JoinChat {
+	fn from(value: JoinChat) -> Self {
+		Self {
+			chatroom: value.chatroom,
+			last_seen_seq_id: value.last_seen_seq_id,
+			last_seen_change_id: None,
+		}
+	}
+}
+
+impl From<C2S> for crate::C2S {
+	fn from(value: C2S) -> Self {
+		match value {
+			C2S::JoinChat(x) => Self::JoinChat(x.into()),
+			C2S::LeaveChat(x) => Self::LeaveChat(x),
+			C2S::SendMessage(x) => Self::SendMessage(x),
+			C2S::FetchHistory(x) => Self::FetchHistory(x),
+			C2S::EditMessage(x) => Self::EditMessage(x),
+			C2S::DeleteMessage(x) => Self::DeleteMessage(x),
+			C2S::Typing(x) => Self::Typing(x),
+			C2S::SetAway(x) => Self::SetAway(x),
+			C2S::QueryPresence(x) => Self::QueryPresence(x),
+			C2S::MarkRead(x) => Self::MarkRead(x),
+			C2S::FetchUnreadCounts(x) => Self::FetchUnreadCounts(x),
+			C2S::AddReaction(x) => Self::AddReaction(x),
+			C2S::RemoveReaction(x) => Self::RemoveReaction(x),
+			C2S::FetchThread(x) => Self::FetchThread(x),
+			C2S::OpenDirectChat(x) => Self::OpenDirectChat(x),
+			C2S::ListChatrooms(x) => Self::ListChatrooms(x),
+			C2S::CreateChatroom(x) => Self::CreateChatroom(x),
+			C2S::RenameChatroom(x) => Self::RenameChatroom(x),
+			C2S::SetChatroomArchived(x) => Self::SetChatroomArchived(x),
+			C2S::DeleteChatroom(x) => Self::DeleteChatroom(x),
+			C2S::FetchChatroomMembers(x) => Self::FetchChatroomMembers(x),
+			C2S::SetMemberRole(x) => Self::SetMemberRole(x),
+			C2S::KickMember(x) => Self::KickMember(x),
+			C2S::BanMember(x) => Self::BanMember(x),
+			C2S::UnbanMember(x) => Self::UnbanMember(x),
+			C2S::MuteMember(x) => Self::MuteMember(x),
+			C2S::UnmuteMember(x) => Self::UnmuteMember(x),
+			C2S::CreateInvite(x) => Self::CreateInvite(x),
+			C2S::RevokeInvite(x) => Self::RevokeInvite(x),
+			C2S::RedeemInvite(x) => Self::RedeemInvite(x),
+		}
+	}
+}
+
+message!(S2C => crate::S2C);
+#[derive(Encode, Decode, Debug)]
+pub enum S2C {
+	Error(Error),
+	NewMessage(ChatMessage),
+	MessageAck(MessageAck),
+	History(History),
+	MessageEdited(MessageEdited),
+	MessageDeleted(MessageDeleted),
+	TypingUpdate(TypingUpdate),
+	PresenceChanged(PresenceChanged),
+	PresenceInfo(PresenceInfo),
+	ReadMarkerUpdated(ReadMarkerUpdated),
+	UnreadCounts(UnreadCounts),
+	ReactionUpdated(ReactionUpdated),
+	Thread(Thread),
+	DirectChatOpened(DirectChatOpened),
+	Chatrooms(Chatrooms),
+	ChatroomCreated(ChatroomCreated),
+	ChatroomListChanged(ChatroomListChanged),
+	ChatroomMembers(ChatroomMembers),
+	MembershipUpdated(MembershipUpdated),
+	InviteCreated(InviteCreated),
+	InviteRedeemed(InviteRedeemed),
+	Rejected(Rejected),
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct MessageEdited {
+	pub id: [u8; 16],
+	pub chatroom: [u8; 16],
+	pub sequence_id: i64,
+	pub message: String,
+	pub edited_at: i64,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct MessageDeleted {
+	pub id: [u8; 16],
+	pub chatroom: [u8; 16],
+	pub sequence_id: i64,
+	pub deleted_at: i64,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct ReactionUpdated {
+	pub message_id: [u8; 16],
+	pub chatroom: [u8; 16],
+	pub sequence_id: i64,
+	pub user_id: [u8; 16],
+	pub emoji: String,
+	pub added: bool,
+	pub count: u32,
+}
+
+impl S2C {
+	/// Returns `None` if the packet didn't exist in v8
+	pub fn from_current(packet: crate::S2C) -> Option<Self> {
+		Some(match packet {
+			crate::S2C::Error(x) => Self::Error(x),
+			crate::S2C::NewMessage(x) => Self::NewMessage(x),
+			crate::S2C::MessageAck(x) => Self::MessageAck(x),
+			crate::S2C::History(x) => Self::History(x),
+			crate::S2C::MessageEdited(x) => Self::MessageEdited(MessageEdited {
+				id: x.id,
+				chatroom: x.chatroom,
+				sequence_id: x.sequence_id,
+				message: x.message,
+				edited_at: x.edited_at,
+			}),
+			crate::S2C::MessageDeleted(x) => Self::MessageDeleted(MessageDeleted {
+				id: x.id,
+				chatroom: x.chatroom,
+				sequence_id: x.sequence_id,
+				deleted_at: x.deleted_at,
+			}),
+			crate::S2C::TypingUpdate(x) => Self::TypingUpdate(x),
+			crate::S2C::PresenceChanged(x) => Self::PresenceChanged(x),
+			crate::S2C::PresenceInfo(x) => Self::PresenceInfo(x),
+			crate::S2C::ReadMarkerUpdated(x) => Self::ReadMarkerUpdated(x),
+			crate::S2C::UnreadCounts(x) => Self::UnreadCounts(x),
+			crate::S2C::ReactionUpdated(x) => Self::ReactionUpdated(ReactionUpdated {
+				message_id: x.message_id,
+				chatroom: x.chatroom,
+				sequence_id: x.sequence_id,
+				user_id: x.user_id,
+				emoji: x.emoji,
+				added: x.added,
+				count: x.count,
+			}),
+			crate::S2C::Thread(x) => Self::Thread(x),
+			crate::S2C::DirectChatOpened(x) => Self::DirectChatOpened(x),
+			crate::S2C::Chatrooms(x) => Self::Chatrooms(x),
+			crate::S2C::ChatroomCreated(x) => Self::ChatroomCreated(x),
+			crate::S2C::ChatroomListChanged(x) => Self::ChatroomListChanged(x),
+			crate::S2C::ChatroomMembers(x) => Self::ChatroomMembers(x),
+			crate::S2C::MembershipUpdated(x) => Self::MembershipUpdated(x),
+			crate::S2C::InviteCreated(x) => Self::InviteCreated(x),
+			crate::S2C::InviteRedeemed(x) => Self::InviteRedeemed(x),
+			crate::S2C::Rejected(x) => Self::Rejected(x),
+			crate::S2C::MessageChanged(_) | crate::S2C::MessageRejected(_) => return None,
+		})
+	}
+}
@@ -1,3 +1,4 @@
+mod capabilities;
 mod r#macro;
 mod message;
 
@@ -7,6 +8,7 @@ use r#macro::{from_variants, message};
 use s2c::*;
 
 pub use bitcode::Error;
+pub use capabilities::Capabilities;
 pub use message::{IntoMessage, Message};
 
 /// Client to server messages
@@ -16,8 +18,20 @@ pub mod s2c;
 
 /// `/auth` endpoint
 pub mod auth;
+pub mod legacy;
 
-pub const VERSION: u32 = 1;
+/// The newest protocol version
+///
+/// Must be bumped whenever the encoding of an existing packet changes,
+/// with the old encoding kept in [`legacy`] for as long as that version is supported.
+///
+/// - v7: [`ChatMessage`] has edits, reactions and replies, [`SendMessage`] has `reply_to`
+/// - v8: errors that dont close the connection are sent in [`Rejected`]
+/// - v9: edits, deletions and reactions have a `change_id` that [`JoinChat`] can resume from
+/// - v10: rejected messages are sent in [`MessageRejected`] with their nonce
+pub const VERSION: u32 = 10;
+/// The oldest protocol version that is still supported
+pub const MIN_VERSION: u32 = 6;
 
 message!(C2S => C2S);
 from_variants! {
@@ -29,7 +43,36 @@ from_variants! {
 /// Additionally this enum serves as a packet direction marker for the [`Message`] and [`IntoMessage`] traits.
 #[derive(Encode, Decode, Debug)]
 pub enum C2S {
+	JoinChat(JoinChat),
+	LeaveChat(LeaveChat),
 	SendMessage(SendMessage),
+	FetchHistory(FetchHistory),
+	EditMessage(EditMessage),
+	DeleteMessage(DeleteMessage),
+	Typing(Typing),
+	SetAway(SetAway),
+	QueryPresence(QueryPresence),
+	MarkRead(MarkRead),
+	FetchUnreadCounts(FetchUnreadCounts),
+	AddReaction(AddReaction),
+	RemoveReaction(RemoveReaction),
+	FetchThread(FetchThread),
+	OpenDirectChat(OpenDirectChat),
+	ListChatrooms(ListChatrooms),
+	CreateChatroom(CreateChatroom),
+	RenameChatroom(RenameChatroom),
+	SetChatroomArchived(SetChatroomArchived),
+	DeleteChatroom(DeleteChatroom),
+	FetchChatroomMembers(FetchChatroomMembers),
+	SetMemberRole(SetMemberRole),
+	KickMember(KickMember),
+	BanMember(BanMember),
+	UnbanMember(UnbanMember),
+	MuteMember(MuteMember),
+	UnmuteMember(UnmuteMember),
+	CreateInvite(CreateInvite),
+	RevokeInvite(RevokeInvite),
+	RedeemInvite(RedeemInvite),
 }
 }
 
@@ -44,6 +87,64 @@ from_variants! {
 #[derive(Encode, Decode, Debug)]
 pub enum S2C {
 	Error(s2c::Error),
-	NewMessage(NewMessage),
+	NewMessage(ChatMessage),
+	MessageAck(MessageAck),
+	History(History),
+	MessageEdited(MessageEdited),
+	MessageDeleted(MessageDeleted),
+	TypingUpdate(TypingUpdate),
+	PresenceChanged(PresenceChanged),
+	PresenceInfo(PresenceInfo),
+	ReadMarkerUpdated(ReadMarkerUpdated),
+	UnreadCounts(UnreadCounts),
+	ReactionUpdated(ReactionUpdated),
+	Thread(Thread),
+	DirectChatOpened(DirectChatOpened),
+	Chatrooms(Chatrooms),
+	ChatroomCreated(ChatroomCreated),
+	ChatroomListChanged(ChatroomListChanged),
+	ChatroomMembers(ChatroomMembers),
+	MembershipUpdated(MembershipUpdated),
+	InviteCreated(InviteCreated),
+	InviteRedeemed(InviteRedeemed),
+	Rejected(Rejected),
+	MessageChanged(MessageChanged),
+	MessageRejected(MessageRejected),
 }
 }
+
+impl C2S {
+	/// Decodes a packet encoded in the given protocol version
+	pub fn read_versioned(from: &[u8], version: u32) -> Result<Self, Error> {
+		match version {
+			6 => legacy::v6::C2S::read(from).map(Self::from),
+			7 | 8 => legacy::v8::C2S::read(from).map(Self::from),
+			_ => Self::read(from),
+		}
+	}
+}
+
+impl S2C {
+	/// Encodes the packet in the given protocol version.
+	///
+	/// Returns `None` if the packet doesn't exist in that version
+	pub fn write_versioned(self, version: u32) -> Option<Vec<u8>> {
+		let packet = match self {
+			Self::MessageRejected(rejected) if version < 10 => Self::Rejected(Rejected {
+				packet_index: rejected.packet_index,
+				error: rejected.error,
+			}),
+			other => other,
+		};
+		let packet = match packet {
+			Self::Rejected(rejected) if version < 8 => Self::Error(rejected.error),
+			other => other,
+		};
+
+		match version {
+			6 => legacy::v6::S2C::from_current(packet).map(|packet| packet.write()),
+			7 | 8 => legacy::v8::S2C::from_current(packet).map(|packet| packet.write()),
+			_ => Some(packet.write()),
+		}
+	}
+}
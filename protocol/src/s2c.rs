@@ -1,8 +1,10 @@
-use crate::S2C;
 use crate::r#macro::message;
+use crate::{Capabilities, S2C};
 use bitcode::{Decode, Encode};
 use thiserror::Error;
 
+/// Sent on its own right before the server closes the connection,
+/// or in [`Rejected`] if only a single packet failed
 #[derive(Encode, Decode, Debug, Error)]
 pub enum Error {
 	#[error("invalid packet")]
@@ -15,16 +17,373 @@ pub enum Error {
 	TimedOut,
 	#[error("unexpected text frame")]
 	TextFrame,
+	#[error("not a member of the chatroom")]
+	NotChatroomMember,
+	#[error("message not found")]
+	MessageNotFound,
+	#[error("not the author of the message")]
+	NotMessageAuthor,
+	#[error("chatroom not joined")]
+	ChatNotJoined,
+	#[error("invalid reaction")]
+	InvalidReaction,
+	#[error("user not found")]
+	UserNotFound,
+	#[error("cannot open a direct chat with yourself")]
+	DirectChatWithSelf,
+	#[error("invalid chatroom name")]
+	InvalidChatroomName,
+	#[error("not the owner of the chatroom")]
+	NotChatroomOwner,
+	#[error("chatroom is archived")]
+	ChatroomArchived,
+	#[error("not permitted in the chatroom")]
+	NotPermitted,
+	#[error("muted in the chatroom")]
+	Muted,
+	#[error("invalid mute duration")]
+	InvalidMuteDuration,
+	#[error("invite not found or expired")]
+	InvalidInvite,
+	#[error("invalid invite expiry or max uses")]
+	InvalidInviteOptions,
+	#[error("banned from the chatroom")]
+	BannedFromChatroom,
+	#[error("sending too fast, retry after {retry_after}s")]
+	RateLimited {
+		/// seconds until the packet can be sent again
+		retry_after: u32,
+	},
 }
 
+/// A packet of the client couldn't be handled, the connection stays open
+///
+/// Sent as a plain [`Error`] before v3
+#[derive(Encode, Decode, Debug)]
+pub struct Rejected {
+	/// Index of the rejected packet, counting all packets the client sent
+	/// after [`Authenticate`][crate::c2s::Authenticate] starting from 0
+	pub packet_index: u64,
+	pub error: Error,
+}
+
+/// A [`SendMessage`][crate::c2s::SendMessage] couldn't be stored, the connection stays open
+///
+/// Sent as a plain [`Rejected`] before v5
+#[derive(Encode, Decode, Debug)]
+pub struct MessageRejected {
+	pub nonce: [u8; 16],
+	/// Same as in [`Rejected`]
+	pub packet_index: u64,
+	pub error: Error,
+}
+
+/// Response to [`Hello`][crate::c2s::Hello]
+///
+/// The format of this packet will never change between protocol versions.
+#[derive(Encode, Decode, Debug)]
+pub enum HelloResponse {
+	/// The connection will continue with this protocol version and capabilities
+	Accepted {
+		version: u32,
+		capabilities: Capabilities,
+	},
+	/// None of the client's protocol versions are supported. The server will close the connection.
+	Unsupported { min_version: u32, max_version: u32 },
+}
+message!(HelloResponse => S2C);
+
 #[derive(Encode, Decode, Debug)]
 pub struct UserInfo {
 	pub username: String,
 }
 message!(UserInfo => S2C);
 
+/// Acknowledges that a [`SendMessage`][crate::c2s::SendMessage] was stored
+#[derive(Encode, Decode, Debug)]
+pub struct MessageAck {
+	pub nonce: [u8; 16],
+	pub message_id: [u8; 16],
+	pub sequence_id: i64,
+}
+
+/// A message stored in a chatroom
 #[derive(Encode, Decode, Debug)]
-pub struct NewMessage {
-	pub user: String,
+pub struct ChatMessage {
+	pub id: [u8; 16],
+	pub chatroom: [u8; 16],
+	pub sequence_id: i64,
+	/// Nil if the author deleted their account
+	pub user_id: [u8; 16],
+	/// Empty if the author deleted their account
+	pub username: String,
+	/// Empty if the message was deleted
+	pub message: String,
+	/// unix timestamp in milliseconds
+	pub sent_at: i64,
+	/// unix timestamp in milliseconds, `None` if the message was never edited
+	pub edited_at: Option<i64>,
+	/// unix timestamp in milliseconds, `None` if the message wasnt deleted
+	pub deleted_at: Option<i64>,
+	/// Only filled in [`History`], [`Thread`] and [`MessageChanged`], new messages are always sent without reactions
+	pub reactions: Vec<ReactionCount>,
+	/// The message this one replies to
+	pub reply_to: Option<[u8; 16]>,
+	/// The first message of the reply chain, `None` if this isnt a reply
+	pub thread_root: Option<[u8; 16]>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct ReactionCount {
+	pub emoji: String,
+	pub count: u32,
+	/// Whether the user is one of the reactors
+	pub reacted: bool,
+}
+
+/// A message in a joined chatroom was edited
+///
+/// Only sent with [`Capabilities::MESSAGE_EDITS`]
+#[derive(Encode, Decode, Debug)]
+pub struct MessageEdited {
+	pub id: [u8; 16],
+	pub chatroom: [u8; 16],
+	pub sequence_id: i64,
+	/// See [`MessageChanged::change_id`]
+	pub change_id: i64,
 	pub message: String,
+	/// unix timestamp in milliseconds
+	pub edited_at: i64,
+}
+
+/// A message in a joined chatroom was deleted
+///
+/// Only sent with [`Capabilities::MESSAGE_EDITS`]
+#[derive(Encode, Decode, Debug)]
+pub struct MessageDeleted {
+	pub id: [u8; 16],
+	pub chatroom: [u8; 16],
+	pub sequence_id: i64,
+	/// See [`MessageChanged::change_id`]
+	pub change_id: i64,
+	/// unix timestamp in milliseconds
+	pub deleted_at: i64,
+}
+
+/// Current state of a message whose edits, deletion or reactions the client missed.
+/// Sent when resuming a chat, or when the server couldn't keep up with the live changes
+///
+/// Only sent with [`Capabilities::MESSAGE_EDITS`] or [`Capabilities::REACTIONS`]
+#[derive(Encode, Decode, Debug)]
+pub struct MessageChanged {
+	/// Increases with every edit, deletion and reaction change in the chatroom.
+	/// The highest one seen can be given in [`JoinChat`][crate::c2s::JoinChat] when resuming
+	pub change_id: i64,
+	/// With the reactions filled in
+	pub message: ChatMessage,
+}
+
+/// Response to [`FetchHistory`][crate::c2s::FetchHistory]
+#[derive(Encode, Decode, Debug)]
+pub struct History {
+	pub chatroom: [u8; 16],
+	/// Ordered by sequence id, oldest first
+	pub messages: Vec<ChatMessage>,
+}
+
+/// Response to [`FetchThread`][crate::c2s::FetchThread]
+#[derive(Encode, Decode, Debug)]
+pub struct Thread {
+	pub chatroom: [u8; 16],
+	pub thread_root: [u8; 16],
+	/// Replies ordered by sequence id, oldest first. Doesnt include the root
+	pub messages: Vec<ChatMessage>,
+}
+
+/// A user in a joined chatroom started or stopped typing
+///
+/// Only sent with [`Capabilities::TYPING`]
+#[derive(Encode, Decode, Debug)]
+pub struct TypingUpdate {
+	pub chatroom: [u8; 16],
+	pub user_id: [u8; 16],
+	pub username: String,
+	/// `false` if the user stopped typing or the typing expired
+	pub typing: bool,
+}
+
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Presence {
+	Online,
+	Away,
+	Offline,
+}
+
+/// A user sharing a chatroom with the user went online, away or offline
+///
+/// Only sent with [`Capabilities::PRESENCE`]
+#[derive(Encode, Decode, Debug)]
+pub struct PresenceChanged {
+	pub user_id: [u8; 16],
+	pub presence: Presence,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct UserPresence {
+	pub user_id: [u8; 16],
+	pub presence: Presence,
+}
+
+/// Response to [`QueryPresence`][crate::c2s::QueryPresence]
+///
+/// Users that dont share any chatroom with the user are left out
+#[derive(Encode, Decode, Debug)]
+pub struct PresenceInfo {
+	pub users: Vec<UserPresence>,
 }
+
+/// The read marker of the user moved in a chatroom, possibly from another device
+///
+/// Only sent with [`Capabilities::READ_MARKERS`]
+#[derive(Encode, Decode, Debug)]
+pub struct ReadMarkerUpdated {
+	pub chatroom: [u8; 16],
+	pub last_read_seq_id: i64,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct UnreadCount {
+	pub chatroom: [u8; 16],
+	/// `None` if nothing was marked as read yet
+	pub last_read_seq_id: Option<i64>,
+	pub unread: u64,
+}
+
+/// Response to [`FetchUnreadCounts`][crate::c2s::FetchUnreadCounts]
+#[derive(Encode, Decode, Debug)]
+pub struct UnreadCounts {
+	pub chatrooms: Vec<UnreadCount>,
+}
+
+/// Someone added or removed a reaction on a message in a joined chatroom
+///
+/// Only sent with [`Capabilities::REACTIONS`]
+#[derive(Encode, Decode, Debug)]
+pub struct ReactionUpdated {
+	pub message_id: [u8; 16],
+	pub chatroom: [u8; 16],
+	pub sequence_id: i64,
+	/// See [`MessageChanged::change_id`]
+	pub change_id: i64,
+	pub user_id: [u8; 16],
+	pub emoji: String,
+	/// `false` if the reaction was removed
+	pub added: bool,
+	/// Number of reactions with this emoji after the change
+	pub count: u32,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct DirectChatInfo {
+	pub chatroom: [u8; 16],
+	/// The other user, nil if they deleted their account
+	pub user_id: [u8; 16],
+	/// Empty if the other user deleted their account
+	pub username: String,
+	/// `None` if there are no messages yet
+	pub last_seq_id: Option<i64>,
+}
+
+/// Response to [`OpenDirectChat`][crate::c2s::OpenDirectChat]
+#[derive(Encode, Decode, Debug)]
+pub struct DirectChatOpened(pub DirectChatInfo);
+
+#[derive(Encode, Decode, Debug)]
+pub struct ChatroomInfo {
+	pub id: [u8; 16],
+	pub name: String,
+	pub archived: bool,
+	/// `None` if there are no messages yet
+	pub last_seq_id: Option<i64>,
+}
+
+/// Response to [`ListChatrooms`][crate::c2s::ListChatrooms]
+#[derive(Encode, Decode, Debug)]
+pub struct Chatrooms {
+	pub groups: Vec<ChatroomInfo>,
+	pub direct_chats: Vec<DirectChatInfo>,
+}
+
+/// Response to [`CreateChatroom`][crate::c2s::CreateChatroom]
+#[derive(Encode, Decode, Debug)]
+pub struct ChatroomCreated(pub ChatroomInfo);
+
+/// The chatroom list of the user changed, possibly because of another device
+///
+/// Only sent with [`Capabilities::CHATROOM_UPDATES`]
+#[derive(Encode, Decode, Debug)]
+pub enum ChatroomListChanged {
+	Added(ChatroomInfo),
+	DirectChatAdded(DirectChatInfo),
+	Updated {
+		id: [u8; 16],
+		name: String,
+		archived: bool,
+	},
+	/// The user is no longer a member, or the chatroom was deleted.
+	/// If the chatroom was joined, it is left automatically.
+	Removed {
+		id: [u8; 16],
+	},
+}
+
+/// Ordered by privilege
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChatroomRole {
+	Member,
+	Moderator,
+	Owner,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub struct ChatroomMemberInfo {
+	pub user_id: [u8; 16],
+	pub username: String,
+	pub role: ChatroomRole,
+	/// unix timestamp in milliseconds, `None` if not muted
+	pub muted_until: Option<i64>,
+}
+
+/// Response to [`FetchChatroomMembers`][crate::c2s::FetchChatroomMembers]
+#[derive(Encode, Decode, Debug)]
+pub struct ChatroomMembers {
+	pub chatroom: [u8; 16],
+	pub members: Vec<ChatroomMemberInfo>,
+}
+
+/// The role or mute of the user in a chatroom changed
+///
+/// Only sent with [`Capabilities::MODERATION`]
+#[derive(Encode, Decode, Debug)]
+pub struct MembershipUpdated {
+	pub chatroom: [u8; 16],
+	pub role: ChatroomRole,
+	/// unix timestamp in milliseconds, `None` if not muted
+	pub muted_until: Option<i64>,
+}
+
+/// Response to [`CreateInvite`][crate::c2s::CreateInvite]
+#[derive(Encode, Decode, Debug)]
+pub struct InviteCreated {
+	pub chatroom: [u8; 16],
+	pub code: String,
+	/// Public landing page of the invite
+	pub link: String,
+	/// unix timestamp in milliseconds, `None` if it never expires
+	pub expires_at: Option<i64>,
+	pub max_uses: Option<u32>,
+}
+
+/// Response to [`RedeemInvite`][crate::c2s::RedeemInvite], also sent if the user already was a member
+#[derive(Encode, Decode, Debug)]
+pub struct InviteRedeemed(pub ChatroomInfo);
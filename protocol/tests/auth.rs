@@ -0,0 +1,67 @@
+use protocol::auth::{v1, v2};
+use serde_json::json;
+use uuid::Uuid;
+
+const A: Uuid = Uuid::from_u128(1);
+const B: Uuid = Uuid::from_u128(2);
+
+#[test]
+fn v2_tokens() {
+	let tokens = v2::Tokens {
+		access_token: A,
+		access_token_expires_in: 900,
+		refresh_token: B,
+	};
+
+	assert_eq!(
+		serde_json::to_value(&tokens).unwrap(),
+		json!({
+			"access_token": A,
+			"access_token_expires_in": 900,
+			"refresh_token": B,
+		})
+	);
+}
+
+#[test]
+fn v2_refresh_request() {
+	let request: v2::RefreshRequest =
+		serde_json::from_value(json!({ "refresh_token": A })).unwrap();
+
+	assert_eq!(request.refresh_token, A);
+}
+
+#[test]
+fn v2_login_response() {
+	let success = json!({
+		"access_token": A,
+		"access_token_expires_in": 900,
+		"refresh_token": B,
+	});
+	match serde_json::from_value(success).unwrap() {
+		v2::LoginResponse::Success(tokens) => {
+			assert_eq!(tokens.access_token, A);
+			assert_eq!(tokens.refresh_token, B);
+		}
+		other => panic!("{other:?}"),
+	}
+
+	let two_factor = json!({ "challenge": A });
+	match serde_json::from_value(two_factor).unwrap() {
+		v2::LoginResponse::TwoFactorRequired(challenge) => assert_eq!(challenge.challenge, A),
+		other => panic!("{other:?}"),
+	}
+}
+
+#[test]
+fn v1_login_response() {
+	match serde_json::from_value(json!({ "auth_token": A })).unwrap() {
+		v1::LoginResponse::Success(success) => assert_eq!(success.auth_token, A),
+		other => panic!("{other:?}"),
+	}
+
+	match serde_json::from_value(json!({ "challenge": A })).unwrap() {
+		v1::LoginResponse::TwoFactorRequired(challenge) => assert_eq!(challenge.challenge, A),
+		other => panic!("{other:?}"),
+	}
+}
@@ -0,0 +1,208 @@
+use protocol::{
+	C2S, Message, S2C, c2s,
+	legacy::{v6, v8},
+	s2c,
+};
+
+#[test]
+fn v6_send_message() {
+	let bytes = v6::C2S::SendMessage(v6::SendMessage {
+		chatroom: [1; 16],
+		nonce: [2; 16],
+		message: "hello".to_owned(),
+	})
+	.write();
+
+	match C2S::read_versioned(&bytes, 6).unwrap() {
+		C2S::SendMessage(c2s::SendMessage {
+			chatroom,
+			nonce,
+			message,
+			reply_to,
+		}) => {
+			assert_eq!(chatroom, [1; 16]);
+			assert_eq!(nonce, [2; 16]);
+			assert_eq!(message, "hello");
+			assert_eq!(reply_to, None);
+		}
+		other => panic!("unexpected packet {other:?}"),
+	}
+
+	// the newest encoding is different
+	assert!(C2S::read_versioned(&bytes, protocol::VERSION).is_err());
+}
+
+#[test]
+fn v6_new_message() {
+	let packet = S2C::NewMessage(s2c::ChatMessage {
+		id: [1; 16],
+		chatroom: [2; 16],
+		sequence_id: 3,
+		user_id: [4; 16],
+		username: "user".to_owned(),
+		message: "hello".to_owned(),
+		sent_at: 5,
+		edited_at: Some(6),
+		deleted_at: None,
+		reactions: Vec::new(),
+		reply_to: Some([7; 16]),
+		thread_root: Some([7; 16]),
+	});
+
+	let bytes = packet.write_versioned(6).unwrap();
+
+	match v6::S2C::read(&bytes).unwrap() {
+		v6::S2C::NewMessage(msg) => {
+			assert_eq!(msg.id, [1; 16]);
+			assert_eq!(msg.sequence_id, 3);
+			assert_eq!(msg.username, "user");
+			assert_eq!(msg.message, "hello");
+			assert_eq!(msg.sent_at, 5);
+		}
+		other => panic!("unexpected packet {other:?}"),
+	}
+}
+
+#[test]
+fn v6_unknown_packets() {
+	let packet = S2C::UnreadCounts(s2c::UnreadCounts {
+		chatrooms: Vec::new(),
+	});
+	assert!(packet.write_versioned(6).is_none());
+
+	let bytes = S2C::Error(s2c::Error::Muted).write_versioned(6).unwrap();
+	assert!(matches!(
+		v6::S2C::read(&bytes).unwrap(),
+		v6::S2C::Error(v6::Error::NotChatroomMember)
+	));
+}
+
+#[test]
+fn v7_rejected() {
+	let rejected = || {
+		S2C::Rejected(s2c::Rejected {
+			packet_index: 1,
+			error: s2c::Error::MessageNotFound,
+		})
+	};
+
+	let bytes = rejected().write_versioned(7).unwrap();
+	assert!(matches!(
+		S2C::read(&bytes).unwrap(),
+		S2C::Error(s2c::Error::MessageNotFound)
+	));
+
+	let bytes = rejected().write_versioned(protocol::VERSION).unwrap();
+	assert!(matches!(
+		S2C::read(&bytes).unwrap(),
+		S2C::Rejected(s2c::Rejected {
+			packet_index: 1,
+			error: s2c::Error::MessageNotFound,
+		})
+	));
+}
+
+#[test]
+fn v8_join_chat() {
+	let bytes = v8::C2S::JoinChat(v8::JoinChat {
+		chatroom: [1; 16],
+		last_seen_seq_id: Some(2),
+	})
+	.write();
+
+	for version in [6, 7, 8] {
+		match C2S::read_versioned(&bytes, version).unwrap() {
+			C2S::JoinChat(join_chat) => {
+				assert_eq!(join_chat.chatroom, [1; 16]);
+				assert_eq!(join_chat.last_seen_seq_id, Some(2));
+				assert_eq!(join_chat.last_seen_change_id, None);
+			}
+			other => panic!("unexpected packet {other:?}"),
+		}
+	}
+}
+
+#[test]
+fn v8_changes() {
+	let bytes = S2C::MessageDeleted(s2c::MessageDeleted {
+		id: [1; 16],
+		chatroom: [2; 16],
+		sequence_id: 3,
+		change_id: 4,
+		deleted_at: 5,
+	})
+	.write_versioned(8)
+	.unwrap();
+
+	match v8::S2C::read(&bytes).unwrap() {
+		v8::S2C::MessageDeleted(msg) => {
+			assert_eq!(msg.id, [1; 16]);
+			assert_eq!(msg.sequence_id, 3);
+			assert_eq!(msg.deleted_at, 5);
+		}
+		other => panic!("unexpected packet {other:?}"),
+	}
+
+	let packet = S2C::MessageChanged(s2c::MessageChanged {
+		change_id: 4,
+		message: s2c::ChatMessage {
+			id: [1; 16],
+			chatroom: [2; 16],
+			sequence_id: 3,
+			user_id: [4; 16],
+			username: "user".to_owned(),
+			message: String::new(),
+			sent_at: 5,
+			edited_at: None,
+			deleted_at: Some(6),
+			reactions: Vec::new(),
+			reply_to: None,
+			thread_root: None,
+		},
+	});
+	assert!(packet.write_versioned(8).is_none());
+}
+
+#[test]
+fn v9_message_rejected() {
+	let rejected = || {
+		S2C::MessageRejected(s2c::MessageRejected {
+			nonce: [1; 16],
+			packet_index: 2,
+			error: s2c::Error::Muted,
+		})
+	};
+
+	let bytes = rejected().write_versioned(9).unwrap();
+	assert!(matches!(
+		S2C::read(&bytes).unwrap(),
+		S2C::Rejected(s2c::Rejected {
+			packet_index: 2,
+			error: s2c::Error::Muted,
+		})
+	));
+
+	let bytes = rejected().write_versioned(8).unwrap();
+	assert!(matches!(
+		v8::S2C::read(&bytes).unwrap(),
+		v8::S2C::Rejected(s2c::Rejected {
+			packet_index: 2,
+			error: s2c::Error::Muted,
+		})
+	));
+
+	let bytes = rejected().write_versioned(7).unwrap();
+	assert!(matches!(
+		v8::S2C::read(&bytes).unwrap(),
+		v8::S2C::Error(s2c::Error::Muted)
+	));
+
+	let bytes = rejected().write_versioned(protocol::VERSION).unwrap();
+	match S2C::read(&bytes).unwrap() {
+		S2C::MessageRejected(rejected) => {
+			assert_eq!(rejected.nonce, [1; 16]);
+			assert_eq!(rejected.packet_index, 2);
+		}
+		other => panic!("unexpected packet {other:?}"),
+	}
+}
@@ -19,6 +19,14 @@ fn main() {
 		&output_dir.join("email_verification_code.html"),
 		&pages::email_verification::code_page(0123),
 	);
+	save(
+		&output_dir.join("invite.html"),
+		&pages::invite::invite_page("general", "aB3dE5gH7j"),
+	);
+	save(
+		&output_dir.join("invite_invalid.html"),
+		pages::invite::invite_not_found_page(),
+	);
 
 	// emails
 	//////////
@@ -33,6 +41,22 @@ fn main() {
 		&output_dir.join("account_reminder.html"),
 		&pages::email::account_reminder(),
 	);
+	save(
+		&output_dir.join("password_reset.html"),
+		&pages::email::password_reset("Xq7Lm2Vb9Rt4Kd8Pw3Zn6Hc1Jf5Sy0Ga"),
+	);
+	save(
+		&output_dir.join("email_change_code.html"),
+		&pages::email::email_change_code("https://example.com/"),
+	);
+	save(
+		&output_dir.join("email_changed.html"),
+		&pages::email::email_changed("new@example.com"),
+	);
+	save(
+		&output_dir.join("account_deletion.html"),
+		&pages::email::account_deletion("November 16, 2025"),
+	);
 }
 
 fn save(output_path: &Path, contents: &str) {
@@ -0,0 +1,10 @@
+CREATE TABLE chatroom_members (
+    chatroom UUID NOT NULL REFERENCES chatrooms(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    joined_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (chatroom, user_id)
+);
+
+-- for looking up all chatrooms of a user
+CREATE INDEX chatroom_members_user_id_idx ON chatroom_members (user_id);
@@ -0,0 +1,59 @@
+-- client generated unique id of a message, so that retrying to send
+-- the same message doesnt insert it twice
+ALTER TABLE messages ADD COLUMN nonce UUID;
+
+-- nonces dedupe retries within a single chatroom. that way the lookup in add_message is covered
+-- by the lock on the chatroom's sequence id, and sends to different chatrooms can't race into the index
+CREATE UNIQUE INDEX messages_chatroom_user_id_nonce_key ON messages (chatroom, user_id, nonce);
+
+DROP FUNCTION add_message(UUID, UUID, UUID, TEXT);
+
+-- YOU MUST USE THIS FUNCTION TO ADD NEW MESSAGES
+-- returns the id and sequential id of the newly added message,
+-- or of the already existing one if the user already sent a message with the same nonce in the chatroom
+CREATE FUNCTION add_message(
+    p_id UUID,
+    p_chatroom UUID,
+    p_user_id UUID,
+    p_message TEXT,
+    p_nonce UUID,
+    OUT message_id UUID,
+    OUT message_sequence_id BIGINT
+) AS $$
+DECLARE
+    next_id BIGINT;
+BEGIN
+    -- Ensure a sequence id row exists
+    INSERT INTO messages_sequential_ids (chatroom)
+    VALUES (p_chatroom)
+    ON CONFLICT (chatroom) DO NOTHING;
+
+    SELECT next_sequence_id
+    INTO next_id
+    FROM messages_sequential_ids
+    WHERE chatroom = p_chatroom
+    FOR UPDATE;
+
+    -- the lock above also makes sure that a retry can't race with the original message
+    SELECT m.id, m.sequence_id
+    INTO message_id, message_sequence_id
+    FROM messages AS m
+    WHERE m.chatroom = p_chatroom AND m.user_id = p_user_id AND m.nonce = p_nonce;
+
+    IF FOUND THEN
+        RETURN;
+    END IF;
+
+    -- Use the variable to insert the new message
+    INSERT INTO messages (id, chatroom, user_id, message, sequence_id, nonce)
+    VALUES (p_id, p_chatroom, p_user_id, p_message, next_id, p_nonce);
+
+    -- Update the sequence table
+    UPDATE messages_sequential_ids
+    SET next_sequence_id = next_id + 1
+    WHERE chatroom = p_chatroom;
+
+    message_id := p_id;
+    message_sequence_id := next_id;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,123 @@
+ALTER TABLE messages
+    ADD COLUMN edited_at TIMESTAMPTZ,
+    -- deleted messages are kept as tombstones with their content removed,
+    -- so that sequence ids stay contiguous
+    ADD COLUMN deleted_at TIMESTAMPTZ;
+
+-- every edit and deletion of a message gets the next change id of its chatroom,
+-- so that listeners can fetch the messages changed since the last change they received.
+-- NULL if the message was never changed
+ALTER TABLE messages
+    ADD COLUMN change_id BIGINT;
+
+CREATE INDEX messages_chatroom_change_id_idx ON messages (chatroom, change_id)
+    WHERE change_id IS NOT NULL;
+
+ALTER TABLE messages_sequential_ids
+    ADD COLUMN next_change_id BIGINT NOT NULL DEFAULT 0;
+
+-- the row lock is held until the end of the transaction, same as in add_message,
+-- so change ids are committed (and notified) in order
+CREATE FUNCTION next_message_change_id(p_chatroom UUID) RETURNS BIGINT AS $$
+DECLARE
+    next_id BIGINT;
+BEGIN
+    UPDATE messages_sequential_ids
+    SET next_change_id = next_change_id + 1
+    WHERE chatroom = p_chatroom
+    RETURNING next_change_id - 1 INTO next_id;
+
+    RETURN next_id;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE FUNCTION set_message_change_id() RETURNS TRIGGER AS $$
+BEGIN
+  NEW.change_id := next_message_change_id(NEW.chatroom);
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER messages_change_id_trigger
+    BEFORE UPDATE OF message, deleted_at ON messages
+    FOR EACH ROW EXECUTE FUNCTION set_message_change_id();
+
+CREATE OR REPLACE FUNCTION notify_new_message() RETURNS TRIGGER AS $$
+DECLARE
+  payload TEXT;
+BEGIN
+  payload := jsonb_build_object(
+    'kind', 'new',
+    'id', NEW.id,
+    'sequence_id', NEW.sequence_id,
+    'user_id', NEW.user_id,
+    'message', NEW.message,
+    'sent_at', NEW.sent_at
+  )::text;
+
+  -- pg_notify's limit is strictly less than 8000 bytes.
+  IF octet_length(payload) >= 8000 THEN
+    payload := jsonb_build_object(
+      'kind', 'new',
+      'id', NEW.id,
+      'sequence_id', NEW.sequence_id,
+      'user_id', NEW.user_id,
+      -- no message
+      'sent_at', NEW.sent_at
+    )::text;
+  END IF;
+
+  PERFORM pg_notify(
+    'chat-' || NEW.chatroom,
+    payload
+  );
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE FUNCTION notify_message_update() RETURNS TRIGGER AS $$
+DECLARE
+  payload TEXT;
+BEGIN
+  IF NEW.deleted_at IS NOT NULL THEN
+    payload := jsonb_build_object(
+      'kind', 'delete',
+      'id', NEW.id,
+      'sequence_id', NEW.sequence_id,
+      'change_id', NEW.change_id,
+      'deleted_at', NEW.deleted_at
+    )::text;
+  ELSE
+    payload := jsonb_build_object(
+      'kind', 'edit',
+      'id', NEW.id,
+      'sequence_id', NEW.sequence_id,
+      'change_id', NEW.change_id,
+      'message', NEW.message,
+      'edited_at', NEW.edited_at
+    )::text;
+
+    -- pg_notify's limit is strictly less than 8000 bytes.
+    IF octet_length(payload) >= 8000 THEN
+      payload := jsonb_build_object(
+        'kind', 'edit',
+        'id', NEW.id,
+        'sequence_id', NEW.sequence_id,
+        'change_id', NEW.change_id,
+        -- no message
+        'edited_at', NEW.edited_at
+      )::text;
+    END IF;
+  END IF;
+
+  PERFORM pg_notify(
+    'chat-' || NEW.chatroom,
+    payload
+  );
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER messages_update_trigger
+    AFTER UPDATE OF message, deleted_at ON messages
+    FOR EACH ROW EXECUTE FUNCTION notify_message_update();
@@ -0,0 +1,88 @@
+CREATE TYPE presence AS ENUM ('online', 'away', 'offline');
+
+-- one row per connected websocket
+CREATE TABLE presence_sockets (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    away BOOLEAN NOT NULL DEFAULT FALSE,
+    -- refreshed periodically while the socket is alive, so that sockets
+    -- of server instances that died without cleaning up expire
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX presence_sockets_user_id_idx ON presence_sockets (user_id);
+
+-- a user is online if any of their sockets is not away
+CREATE FUNCTION user_presence(p_user_id UUID) RETURNS presence AS $$
+  SELECT CASE
+    WHEN bool_or(NOT away) THEN 'online'::presence
+    WHEN bool_or(away) THEN 'away'::presence
+    ELSE 'offline'::presence
+  END
+  FROM presence_sockets
+  WHERE user_id = p_user_id AND expires_at > NOW()
+$$ LANGUAGE sql STABLE;
+
+CREATE FUNCTION notify_presence() RETURNS TRIGGER AS $$
+DECLARE
+  v_user_id UUID;
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    v_user_id := OLD.user_id;
+  ELSE
+    v_user_id := NEW.user_id;
+  END IF;
+
+  -- the presence might not have changed (another device connecting etc.),
+  -- listeners have to filter that out themselves
+  PERFORM pg_notify(
+    'presence',
+    jsonb_build_object(
+      'user_id', v_user_id,
+      'presence', user_presence(v_user_id)
+    )::text
+  );
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER presence_sockets_insert_delete_trigger
+    AFTER INSERT OR DELETE ON presence_sockets
+    FOR EACH ROW EXECUTE FUNCTION notify_presence();
+
+-- refreshing expires_at shouldnt notify anyone
+CREATE TRIGGER presence_sockets_update_trigger
+    AFTER UPDATE OF away ON presence_sockets
+    FOR EACH ROW
+    WHEN (OLD.away IS DISTINCT FROM NEW.away)
+    EXECUTE FUNCTION notify_presence();
+
+-- the other members are notified about members joining and leaving,
+-- so that they can follow their presence
+CREATE FUNCTION notify_chatroom_co_members() RETURNS TRIGGER AS $$
+DECLARE
+  v_row chatroom_members%ROWTYPE;
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    v_row := OLD;
+  ELSE
+    v_row := NEW;
+  END IF;
+
+  PERFORM pg_notify(
+    'user-' || m.user_id,
+    jsonb_build_object(
+      'kind', CASE WHEN TG_OP = 'DELETE' THEN 'member_removed' ELSE 'member_added' END,
+      'chatroom', v_row.chatroom,
+      'user_id', v_row.user_id
+    )::text
+  )
+  FROM chatroom_members m
+  WHERE m.chatroom = v_row.chatroom AND m.user_id != v_row.user_id;
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER chatroom_co_members_trigger
+    AFTER INSERT OR DELETE ON chatroom_members
+    FOR EACH ROW EXECUTE FUNCTION notify_chatroom_co_members();
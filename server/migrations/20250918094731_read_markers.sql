@@ -0,0 +1,27 @@
+CREATE TABLE read_markers (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    chatroom UUID NOT NULL REFERENCES chatrooms(id) ON DELETE CASCADE,
+    -- all messages up to and including this one were read
+    last_read_seq_id BIGINT NOT NULL,
+
+    PRIMARY KEY (user_id, chatroom)
+);
+
+-- notifies all connections of the user, so that other devices can update too
+CREATE FUNCTION notify_read_marker() RETURNS TRIGGER AS $$
+BEGIN
+  PERFORM pg_notify(
+    'user-' || NEW.user_id,
+    jsonb_build_object(
+      'kind', 'read_marker',
+      'chatroom', NEW.chatroom,
+      'last_read_seq_id', NEW.last_read_seq_id
+    )::text
+  );
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER read_markers_trigger
+    AFTER INSERT OR UPDATE ON read_markers
+    FOR EACH ROW EXECUTE FUNCTION notify_read_marker();
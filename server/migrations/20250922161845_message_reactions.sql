@@ -0,0 +1,70 @@
+CREATE TABLE message_reactions (
+    message_id UUID NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    emoji VARCHAR(64) NOT NULL,
+    reacted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (message_id, user_id, emoji)
+);
+
+-- reaction changes go through the chatroom channel, same as messages.
+-- concurrent reactions on the same message are serialized by locking the message row,
+-- so that each of them counts the ones committed before it
+CREATE FUNCTION notify_message_reaction() RETURNS TRIGGER AS $$
+DECLARE
+  v_row message_reactions%ROWTYPE;
+  v_chatroom UUID;
+  v_sequence_id BIGINT;
+  v_change_id BIGINT;
+  v_count BIGINT;
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    v_row := OLD;
+  ELSE
+    v_row := NEW;
+  END IF;
+
+  -- NO KEY UPDATE doesnt conflict with the KEY SHARE lock that inserting
+  -- the reaction already took through the foreign key, so it cant deadlock
+  SELECT chatroom, sequence_id
+  INTO v_chatroom, v_sequence_id
+  FROM messages
+  WHERE id = v_row.message_id
+  FOR NO KEY UPDATE;
+
+  -- the whole message is being deleted
+  IF NOT FOUND THEN
+    RETURN NULL;
+  END IF;
+
+  -- a change of the message too, but without touching the columns that fire the update triggers
+  UPDATE messages
+  SET change_id = next_message_change_id(v_chatroom)
+  WHERE id = v_row.message_id
+  RETURNING change_id INTO v_change_id;
+
+  SELECT count(*)
+  INTO v_count
+  FROM message_reactions
+  WHERE message_id = v_row.message_id AND emoji = v_row.emoji;
+
+  PERFORM pg_notify(
+    'chat-' || v_chatroom,
+    jsonb_build_object(
+      'kind', 'reaction',
+      'id', v_row.message_id,
+      'sequence_id', v_sequence_id,
+      'change_id', v_change_id,
+      'user_id', v_row.user_id,
+      'emoji', v_row.emoji,
+      'added', TG_OP = 'INSERT',
+      'count', v_count
+    )::text
+  );
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER message_reactions_trigger
+    AFTER INSERT OR DELETE ON message_reactions
+    FOR EACH ROW EXECUTE FUNCTION notify_message_reaction();
@@ -0,0 +1,110 @@
+ALTER TABLE messages
+    ADD COLUMN reply_to UUID REFERENCES messages(id) ON DELETE SET NULL,
+    -- the first message of the reply chain, all messages with the same
+    -- thread root are in the same thread
+    ADD COLUMN thread_root UUID REFERENCES messages(id) ON DELETE SET NULL;
+
+CREATE INDEX messages_thread_root_sequence_id_idx ON messages (thread_root, sequence_id)
+    WHERE thread_root IS NOT NULL;
+
+DROP FUNCTION add_message(UUID, UUID, UUID, TEXT, UUID);
+
+-- YOU MUST USE THIS FUNCTION TO ADD NEW MESSAGES
+-- returns the id and sequential id of the newly added message,
+-- or of the already existing one if the user already sent a message with the same nonce in the chatroom
+CREATE FUNCTION add_message(
+    p_id UUID,
+    p_chatroom UUID,
+    p_user_id UUID,
+    p_message TEXT,
+    p_nonce UUID,
+    p_reply_to UUID,
+    OUT message_id UUID,
+    OUT message_sequence_id BIGINT
+) AS $$
+DECLARE
+    next_id BIGINT;
+    v_thread_root UUID;
+BEGIN
+    -- Ensure a sequence id row exists
+    INSERT INTO messages_sequential_ids (chatroom)
+    VALUES (p_chatroom)
+    ON CONFLICT (chatroom) DO NOTHING;
+
+    SELECT next_sequence_id
+    INTO next_id
+    FROM messages_sequential_ids
+    WHERE chatroom = p_chatroom
+    FOR UPDATE;
+
+    -- the lock above also makes sure that a retry can't race with the original message
+    SELECT m.id, m.sequence_id
+    INTO message_id, message_sequence_id
+    FROM messages AS m
+    WHERE m.chatroom = p_chatroom AND m.user_id = p_user_id AND m.nonce = p_nonce;
+
+    IF FOUND THEN
+        RETURN;
+    END IF;
+
+    IF p_reply_to IS NOT NULL THEN
+        SELECT COALESCE(m.thread_root, m.id)
+        INTO v_thread_root
+        FROM messages AS m
+        WHERE m.id = p_reply_to AND m.chatroom = p_chatroom;
+
+        IF NOT FOUND THEN
+            RAISE EXCEPTION 'replied message % is not in chatroom %', p_reply_to, p_chatroom;
+        END IF;
+    END IF;
+
+    -- Use the variable to insert the new message
+    INSERT INTO messages (id, chatroom, user_id, message, sequence_id, nonce, reply_to, thread_root)
+    VALUES (p_id, p_chatroom, p_user_id, p_message, next_id, p_nonce, p_reply_to, v_thread_root);
+
+    -- Update the sequence table
+    UPDATE messages_sequential_ids
+    SET next_sequence_id = next_id + 1
+    WHERE chatroom = p_chatroom;
+
+    message_id := p_id;
+    message_sequence_id := next_id;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION notify_new_message() RETURNS TRIGGER AS $$
+DECLARE
+  payload TEXT;
+BEGIN
+  payload := jsonb_build_object(
+    'kind', 'new',
+    'id', NEW.id,
+    'sequence_id', NEW.sequence_id,
+    'user_id', NEW.user_id,
+    'message', NEW.message,
+    'sent_at', NEW.sent_at,
+    'reply_to', NEW.reply_to,
+    'thread_root', NEW.thread_root
+  )::text;
+
+  -- pg_notify's limit is strictly less than 8000 bytes.
+  IF octet_length(payload) >= 8000 THEN
+    payload := jsonb_build_object(
+      'kind', 'new',
+      'id', NEW.id,
+      'sequence_id', NEW.sequence_id,
+      'user_id', NEW.user_id,
+      -- no message
+      'sent_at', NEW.sent_at,
+      'reply_to', NEW.reply_to,
+      'thread_root', NEW.thread_root
+    )::text;
+  END IF;
+
+  PERFORM pg_notify(
+    'chat-' || NEW.chatroom,
+    payload
+  );
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
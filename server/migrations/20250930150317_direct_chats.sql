@@ -0,0 +1,59 @@
+-- chatrooms between exactly two users, there is at most one per pair
+CREATE TABLE direct_chats (
+    chatroom UUID PRIMARY KEY REFERENCES chatrooms(id) ON DELETE CASCADE,
+    -- always ordered, so that the pair is unique regardless of who opened the chat
+    user_a UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    user_b UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    CONSTRAINT direct_chats_users_ordered CHECK (user_a < user_b),
+    CONSTRAINT direct_chats_user_a_user_b_key UNIQUE (user_a, user_b)
+);
+
+CREATE INDEX direct_chats_user_b_idx ON direct_chats (user_b);
+
+-- returns the direct chat of the two users, creating it with both
+-- of them as members if it doesnt exist yet
+CREATE FUNCTION open_direct_chat(
+    p_new_id UUID,
+    p_user_1 UUID,
+    p_user_2 UUID
+) RETURNS UUID AS $$
+DECLARE
+    v_user_a UUID := LEAST(p_user_1, p_user_2);
+    v_user_b UUID := GREATEST(p_user_1, p_user_2);
+    v_chatroom UUID;
+BEGIN
+    SELECT chatroom
+    INTO v_chatroom
+    FROM direct_chats
+    WHERE user_a = v_user_a AND user_b = v_user_b;
+
+    IF FOUND THEN
+        RETURN v_chatroom;
+    END IF;
+
+    -- direct chats are named after the other user by the clients
+    INSERT INTO chatrooms (id, name) VALUES (p_new_id, '');
+
+    INSERT INTO direct_chats (chatroom, user_a, user_b)
+    VALUES (p_new_id, v_user_a, v_user_b)
+    ON CONFLICT (user_a, user_b) DO NOTHING;
+
+    IF NOT FOUND THEN
+        -- someone else created it concurrently
+        DELETE FROM chatrooms WHERE id = p_new_id;
+
+        SELECT chatroom
+        INTO v_chatroom
+        FROM direct_chats
+        WHERE user_a = v_user_a AND user_b = v_user_b;
+
+        RETURN v_chatroom;
+    END IF;
+
+    INSERT INTO chatroom_members (chatroom, user_id)
+    VALUES (p_new_id, v_user_a), (p_new_id, v_user_b);
+
+    RETURN p_new_id;
+END;
+$$ LANGUAGE plpgsql;
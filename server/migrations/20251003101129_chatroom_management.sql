@@ -0,0 +1,86 @@
+ALTER TABLE chatrooms
+    -- the owner of the chatroom, NULL for direct chats
+    ADD COLUMN created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- archived chatrooms are read only
+    ADD COLUMN archived_at TIMESTAMPTZ;
+
+-- chatrooms from before chatroom management would have no owner, so nobody could manage them.
+-- the earliest member becomes the owner
+UPDATE chatrooms c
+SET created_by = (
+    SELECT m.user_id
+    FROM chatroom_members m
+    WHERE m.chatroom = c.id
+    ORDER BY m.joined_at, m.user_id
+    LIMIT 1
+)
+WHERE NOT EXISTS (SELECT 1 FROM direct_chats d WHERE d.chatroom = c.id);
+
+-- members are notified about chatrooms being added to or removed from their chatroom list
+CREATE FUNCTION notify_chatroom_member() RETURNS TRIGGER AS $$
+DECLARE
+  payload TEXT;
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    PERFORM pg_notify(
+      'user-' || OLD.user_id,
+      jsonb_build_object(
+        'kind', 'chatroom_removed',
+        'chatroom', OLD.chatroom
+      )::text
+    );
+    RETURN NULL;
+  END IF;
+
+  SELECT jsonb_build_object(
+    'kind', 'chatroom_added',
+    'chatroom', c.id,
+    'name', c.name,
+    'archived', c.archived_at IS NOT NULL,
+    'last_seq_id', s.next_sequence_id - 1,
+    'direct_with', (
+      SELECT CASE WHEN d.user_a = NEW.user_id THEN d.user_b ELSE d.user_a END
+      FROM direct_chats d
+      WHERE d.chatroom = c.id
+    )
+  )::text
+  INTO payload
+  FROM chatrooms c
+  LEFT JOIN messages_sequential_ids s ON s.chatroom = c.id
+  WHERE c.id = NEW.chatroom;
+
+  PERFORM pg_notify('user-' || NEW.user_id, payload);
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER chatroom_members_trigger
+    AFTER INSERT OR DELETE ON chatroom_members
+    FOR EACH ROW EXECUTE FUNCTION notify_chatroom_member();
+
+CREATE FUNCTION notify_chatroom_update() RETURNS TRIGGER AS $$
+BEGIN
+  PERFORM pg_notify(
+    'user-' || m.user_id,
+    jsonb_build_object(
+      'kind', 'chatroom_updated',
+      'chatroom', NEW.id,
+      'name', NEW.name,
+      'archived', NEW.archived_at IS NOT NULL
+    )::text
+  )
+  FROM chatroom_members m
+  WHERE m.chatroom = NEW.id;
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER chatrooms_update_trigger
+    AFTER UPDATE OF name, archived_at ON chatrooms
+    FOR EACH ROW
+    WHEN (
+        OLD.name IS DISTINCT FROM NEW.name
+        OR (OLD.archived_at IS NULL) != (NEW.archived_at IS NULL)
+    )
+    EXECUTE FUNCTION notify_chatroom_update();
@@ -0,0 +1,64 @@
+-- ordered by privilege, so roles can be compared
+CREATE TYPE chatroom_role AS ENUM ('member', 'moderator', 'owner');
+
+ALTER TABLE chatroom_members
+    ADD COLUMN role chatroom_role NOT NULL DEFAULT 'member',
+    -- the member cant send messages until then
+    ADD COLUMN muted_until TIMESTAMPTZ;
+
+UPDATE chatroom_members m
+SET role = 'owner'
+FROM chatrooms c
+WHERE c.id = m.chatroom AND c.created_by = m.user_id;
+
+-- banned users cant become members of the chatroom again
+CREATE TABLE chatroom_bans (
+    chatroom UUID NOT NULL REFERENCES chatrooms(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    banned_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    banned_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (chatroom, user_id)
+);
+
+CREATE TYPE moderation_action AS ENUM ('kick', 'ban', 'unban', 'mute', 'unmute', 'set_role');
+
+-- audit log of moderation actions
+CREATE TABLE moderation_log (
+    id UUID PRIMARY KEY,
+    chatroom UUID NOT NULL REFERENCES chatrooms(id) ON DELETE CASCADE,
+    actor UUID REFERENCES users(id) ON DELETE SET NULL,
+    target UUID REFERENCES users(id) ON DELETE SET NULL,
+    action moderation_action NOT NULL,
+    -- only for 'mute'
+    muted_until TIMESTAMPTZ,
+    -- only for 'set_role'
+    role chatroom_role,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX moderation_log_chatroom_idx ON moderation_log (chatroom, created_at);
+
+-- the member is notified when their role or mute changes
+CREATE FUNCTION notify_chatroom_membership_update() RETURNS TRIGGER AS $$
+BEGIN
+  PERFORM pg_notify(
+    'user-' || NEW.user_id,
+    jsonb_build_object(
+      'kind', 'membership_updated',
+      'chatroom', NEW.chatroom,
+      'role', NEW.role,
+      'muted_until', NEW.muted_until
+    )::text
+  );
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER chatroom_members_update_trigger
+    AFTER UPDATE OF role, muted_until ON chatroom_members
+    FOR EACH ROW
+    WHEN (
+        OLD.role IS DISTINCT FROM NEW.role
+        OR OLD.muted_until IS DISTINCT FROM NEW.muted_until
+    )
+    EXECUTE FUNCTION notify_chatroom_membership_update();
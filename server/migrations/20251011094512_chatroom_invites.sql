@@ -0,0 +1,14 @@
+CREATE TABLE chatroom_invites (
+    code TEXT PRIMARY KEY,
+    chatroom UUID NOT NULL REFERENCES chatrooms(id) ON DELETE CASCADE,
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- NULL never expires
+    expires_at TIMESTAMPTZ,
+    -- NULL for unlimited uses
+    max_uses INTEGER,
+    uses INTEGER NOT NULL DEFAULT 0
+);
+
+INSERT INTO cleanup_log (table_name)
+VALUES ('chatroom_invites');
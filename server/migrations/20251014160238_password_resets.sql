@@ -0,0 +1,10 @@
+CREATE TABLE password_resets (
+    -- only a single reset can be pending per user
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    -- the token is sent to the email of the user
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+INSERT INTO cleanup_log (table_name)
+VALUES ('password_resets');
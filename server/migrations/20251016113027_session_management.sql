@@ -0,0 +1,30 @@
+ALTER TABLE active_sessions
+    -- public id of the session, the token itself must stay secret
+    ADD COLUMN id UUID NOT NULL DEFAULT gen_random_uuid() UNIQUE,
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN last_used_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- reported by the client when logging in
+    ADD COLUMN client_name TEXT,
+    ADD COLUMN client_version TEXT,
+    -- the address the session was last used from
+    ADD COLUMN ip TEXT;
+
+CREATE INDEX active_sessions_user_id_idx ON active_sessions (user_id);
+
+-- live connections that authenticated with the session are closed when it is removed
+CREATE FUNCTION notify_session_revoked() RETURNS TRIGGER AS $$
+BEGIN
+  PERFORM pg_notify(
+    'user-' || OLD.user_id,
+    jsonb_build_object(
+      'kind', 'session_revoked',
+      'session_id', OLD.id
+    )::text
+  );
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER active_sessions_delete_trigger
+    AFTER DELETE ON active_sessions
+    FOR EACH ROW EXECUTE FUNCTION notify_session_revoked();
@@ -0,0 +1,12 @@
+-- only a hash of the token is stored, so that leaked rows cant be used as sessions.
+-- existing tokens are converted, the hash is the same as the server computes for the token bytes
+ALTER TABLE active_sessions ADD COLUMN token_hash TEXT;
+
+UPDATE active_sessions SET token_hash = encode(sha256(uuid_send(token)), 'base64');
+
+ALTER TABLE active_sessions
+    DROP CONSTRAINT active_sessions_pkey,
+    DROP COLUMN token,
+    ALTER COLUMN token_hash SET NOT NULL,
+    ADD CONSTRAINT active_sessions_token_hash_key UNIQUE (token_hash),
+    ADD PRIMARY KEY (id);
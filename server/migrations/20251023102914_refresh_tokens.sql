@@ -0,0 +1,15 @@
+-- sessions created through auth v2 have a long-lived refresh token next to the short-lived access token.
+-- for those, expires_at is when the access token expires and the session itself lives until refresh_expires_at.
+-- both are NULL for v1 sessions
+ALTER TABLE active_sessions
+    ADD COLUMN refresh_token_hash TEXT UNIQUE,
+    ADD COLUMN refresh_expires_at TIMESTAMPTZ;
+
+-- refresh tokens that were already exchanged for new ones.
+-- one of these being used again means that it leaked, and the session is revoked
+CREATE TABLE used_refresh_tokens (
+    token_hash TEXT PRIMARY KEY,
+    session_id UUID NOT NULL REFERENCES active_sessions(id) ON DELETE CASCADE
+);
+
+CREATE INDEX used_refresh_tokens_session_id_idx ON used_refresh_tokens (session_id);
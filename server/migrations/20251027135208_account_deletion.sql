@@ -0,0 +1,27 @@
+-- when the account of the user is going to be purged, NULL if no deletion is scheduled.
+-- logging in again before then cancels the deletion
+ALTER TABLE users ADD COLUMN deletion_scheduled_at TIMESTAMPTZ;
+
+CREATE INDEX users_deletion_scheduled_at_idx ON users (deletion_scheduled_at)
+    WHERE deletion_scheduled_at IS NOT NULL;
+
+-- messages of purged users are kept in their chatrooms, but no longer have an author
+ALTER TABLE messages
+    ALTER COLUMN user_id DROP NOT NULL,
+    DROP CONSTRAINT messages_user_id_fkey,
+    ADD CONSTRAINT messages_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE SET NULL;
+
+-- same with direct chats, the purged side is left without a user
+ALTER TABLE direct_chats
+    ALTER COLUMN user_a DROP NOT NULL,
+    ALTER COLUMN user_b DROP NOT NULL,
+    DROP CONSTRAINT direct_chats_user_a_fkey,
+    DROP CONSTRAINT direct_chats_user_b_fkey,
+    ADD CONSTRAINT direct_chats_user_a_fkey
+        FOREIGN KEY (user_a) REFERENCES users(id) ON DELETE SET NULL,
+    ADD CONSTRAINT direct_chats_user_b_fkey
+        FOREIGN KEY (user_b) REFERENCES users(id) ON DELETE SET NULL;
+
+INSERT INTO cleanup_log (table_name)
+VALUES ('users');
@@ -0,0 +1,48 @@
+-- token buckets for rate limiting, shared between all server instances.
+-- the key is made of what is limited and who is doing it, e.g. 'login:ip:127.0.0.1'
+CREATE TABLE rate_limits (
+    key TEXT PRIMARY KEY,
+    -- tokens left at updated_at, refilled continuously since then
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    -- when the bucket is full again, after which the row is the same as no row at all
+    full_at TIMESTAMPTZ NOT NULL
+);
+
+-- takes a token from the bucket of the key, creating a full one if it doesnt exist.
+-- returns 0 if a token was taken, otherwise the seconds until one can be
+CREATE FUNCTION take_rate_limit_token(
+    p_key TEXT,
+    p_capacity DOUBLE PRECISION,
+    p_refill_secs DOUBLE PRECISION -- seconds per token
+) RETURNS DOUBLE PRECISION AS $$
+DECLARE
+    v_tokens DOUBLE PRECISION;
+BEGIN
+    INSERT INTO rate_limits (key, tokens, updated_at, full_at)
+    VALUES (p_key, p_capacity, NOW(), NOW())
+    ON CONFLICT (key) DO NOTHING;
+
+    SELECT LEAST(p_capacity, tokens + EXTRACT(EPOCH FROM NOW() - updated_at) / p_refill_secs)
+    INTO v_tokens
+    FROM rate_limits
+    WHERE key = p_key
+    FOR UPDATE;
+
+    IF v_tokens < 1 THEN
+        RETURN (1 - v_tokens) * p_refill_secs;
+    END IF;
+
+    UPDATE rate_limits
+    SET
+        tokens = v_tokens - 1,
+        updated_at = NOW(),
+        full_at = NOW() + ('1 second'::interval * (p_capacity - v_tokens + 1) * p_refill_secs)
+    WHERE key = p_key;
+
+    RETURN 0;
+END;
+$$ LANGUAGE plpgsql;
+
+INSERT INTO cleanup_log (table_name)
+VALUES ('rate_limits');
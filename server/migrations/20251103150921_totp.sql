@@ -0,0 +1,35 @@
+-- TOTP two-factor authentication. The row is inserted when enrolment starts,
+-- but only required when logging in after it was confirmed with a code
+CREATE TABLE user_totp (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    secret BYTEA NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    -- the time step of the last accepted code, so that a code cant be used twice
+    last_used_step BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- single use codes for when the authenticator is lost
+CREATE TABLE totp_recovery_codes (
+    user_id UUID NOT NULL REFERENCES user_totp(user_id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+
+    PRIMARY KEY (user_id, code_hash)
+);
+
+-- logins with a correct password that are waiting for the second factor
+CREATE TABLE login_challenges (
+    token_hash TEXT PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    attempts SMALLINT NOT NULL DEFAULT 0,
+    expires_at TIMESTAMPTZ NOT NULL,
+    -- passed on to the session once the login is completed
+    client_name TEXT,
+    client_version TEXT,
+    -- the password hash of a password reset, only set once the login is completed
+    -- so that the reset email alone isnt enough
+    new_password TEXT
+);
+
+INSERT INTO cleanup_log (table_name)
+VALUES ('login_challenges');
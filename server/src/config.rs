@@ -1,4 +1,5 @@
-use serde::Deserialize;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use serde::{Deserialize, Deserializer, de::Error};
 use std::{net::SocketAddr, path::Path};
 use tokio::fs::read_to_string;
 use url::Url;
@@ -11,16 +12,86 @@ pub struct Config {
 	pub database_url: String,
 	/// Publicly available base URL for the socket to which the server binds
 	pub public_base_url: Url,
+	/// Extend the lifetime of sessions while they are used on a connection,
+	/// instead of them expiring a fixed time after logging in
+	#[serde(default)]
+	pub sliding_session_expiry: bool,
+	/// Days after requesting the deletion of an account until it is purged
+	#[serde(default = "default_account_deletion_grace_period")]
+	pub account_deletion_grace_period: u32,
+	/// Key with which the TOTP secrets are encrypted in the database, 32 bytes encoded as base64.
+	/// Two-factor authentication can't be enabled without it
+	#[serde(default, deserialize_with = "deserialize_key")]
+	pub totp_secret_key: Option<[u8; 32]>,
+	/// Limits on how often things can be done, shared between all server instances
+	#[serde(default)]
+	pub rate_limits: RateLimitsConfig,
 	/// email config
 	pub email: EmailConfig,
 }
 
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct RateLimitsConfig {
+	/// Login attempts, per IP and per email
+	pub login: RateLimit,
+	/// Emails sent by the auth endpoints, per IP and per email
+	pub email: RateLimit,
+	/// Messages sent, per user
+	pub send_message: RateLimit,
+}
+
+impl Default for RateLimitsConfig {
+	fn default() -> Self {
+		Self {
+			login: RateLimit {
+				burst: 10,
+				per_minute: 2,
+			},
+			email: RateLimit {
+				burst: 3,
+				per_minute: 1,
+			},
+			send_message: RateLimit {
+				burst: 20,
+				per_minute: 60,
+			},
+		}
+	}
+}
+
+/// Token bucket
+#[derive(Deserialize, Clone, Copy)]
+pub struct RateLimit {
+	/// How many can be done at once
+	pub burst: u32,
+	/// How many can be done per minute after the burst is used up, at least 1
+	pub per_minute: u32,
+}
+
 #[derive(Deserialize)]
 pub struct EmailConfig {
 	/// noreply email url
 	pub noreply: Url,
 }
 
+fn default_account_deletion_grace_period() -> u32 {
+	30
+}
+
+fn deserialize_key<'de, D: Deserializer<'de>>(
+	deserializer: D,
+) -> Result<Option<[u8; 32]>, D::Error> {
+	let encoded = String::deserialize(deserializer)?;
+
+	BASE64_STANDARD
+		.decode(encoded)
+		.ok()
+		.and_then(|key| key.try_into().ok())
+		.map(Some)
+		.ok_or_else(|| D::Error::custom("expected 32 bytes encoded as base64"))
+}
+
 pub async fn read_config(path: impl AsRef<Path>) -> anyhow::Result<Config> {
 	Ok(toml::from_str(&read_to_string(path).await?)?)
 }
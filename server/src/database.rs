@@ -3,9 +3,22 @@ use sqlx::{Acquire, Executor, PgConnection, PgPool, Postgres, Transaction, postg
 use std::ops::{Deref, DerefMut};
 
 pub mod active_sessions;
+pub mod chatroom_invites;
+pub mod chatroom_members;
+pub mod chatrooms;
+pub mod direct_chats;
 pub mod email_verifications;
+pub mod login_challenges;
 pub mod message;
+pub mod message_reactions;
+pub mod moderation;
+pub mod password_resets;
+pub mod presence;
+pub mod rate_limits;
+pub mod read_markers;
 pub mod registrations;
+pub mod totp;
+pub mod typing;
 pub mod user;
 
 #[derive(Clone, Debug)]
@@ -1,23 +1,264 @@
 use super::{Database, ExecutorHack};
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
+#[derive(Clone, Debug)]
+pub struct ActiveSession {
+	pub id: Uuid,
+	pub user_id: Uuid,
+	pub created_at: DateTime<Utc>,
+	pub last_used_at: DateTime<Utc>,
+	pub client_name: Option<String>,
+	pub client_version: Option<String>,
+	pub ip: Option<String>,
+}
+
+/// Information about where a session is used from
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SessionMetadata<'a> {
+	pub client_name: Option<&'a str>,
+	pub client_version: Option<&'a str>,
+	pub ip: Option<&'a str>,
+}
+
+#[derive(Debug)]
+pub enum RefreshError {
+	/// The refresh token doesnt exist or the session expired
+	Invalid,
+	/// The refresh token was already used before, the session was revoked
+	Reused,
+}
+
 impl<D: ExecutorHack> Database<D> {
 	pub async fn insert_active_session(
 		&mut self,
+		token_hash: &str,
 		user_id: Uuid,
 		lifetime_hours: u32,
-	) -> sqlx::Result<Uuid> {
-		let session_token = Uuid::now_v7();
-
+		metadata: SessionMetadata<'_>,
+	) -> sqlx::Result<()> {
 		sqlx::query!(
-			r#"INSERT INTO active_sessions (token, user_id, expires_at)
-			VALUES ($1, $2, NOW() + ('1 hour'::interval * $3))"#,
-			session_token,
+			r#"INSERT INTO active_sessions (token_hash, user_id, expires_at, client_name, client_version, ip)
+			VALUES ($1, $2, NOW() + ('1 hour'::interval * $3), $4, $5, $6)"#,
+			token_hash,
 			user_id,
+			lifetime_hours as i64,
+			metadata.client_name,
+			metadata.client_version,
+			metadata.ip
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+	/// Inserts a session that is kept alive with refresh tokens.
+	/// The access token expires after `access_lifetime_minutes`, the session after `lifetime_hours`
+	pub async fn insert_refreshable_session(
+		&mut self,
+		access_token_hash: &str,
+		refresh_token_hash: &str,
+		user_id: Uuid,
+		access_lifetime_minutes: u32,
+		lifetime_hours: u32,
+		metadata: SessionMetadata<'_>,
+	) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"INSERT INTO active_sessions (
+				token_hash, refresh_token_hash, user_id, expires_at, refresh_expires_at, client_name, client_version, ip
+			)
+			VALUES (
+				$1, $2, $3, NOW() + ('1 minute'::interval * $4), NOW() + ('1 hour'::interval * $5), $6, $7, $8
+			)"#,
+			access_token_hash,
+			refresh_token_hash,
+			user_id,
+			access_lifetime_minutes as i64,
+			lifetime_hours as i64,
+			metadata.client_name,
+			metadata.client_version,
+			metadata.ip
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+	/// Replaces both tokens of the session with the refresh token `refresh_token_hash`.
+	/// If the refresh token was already replaced before, the whole session is deleted
+	pub async fn rotate_refresh_token(
+		&mut self,
+		refresh_token_hash: &str,
+		new_access_token_hash: &str,
+		new_refresh_token_hash: &str,
+		access_lifetime_minutes: u32,
+		lifetime_hours: u32,
+		ip: Option<&str>,
+	) -> sqlx::Result<Result<ActiveSession, RefreshError>> {
+		let mut transaction = self.transaction().await?;
+
+		// a concurrent rotation of the same token waits for this one, and then doesnt match anymore
+		let session = sqlx::query_as!(
+			ActiveSession,
+			r#"UPDATE active_sessions
+			SET
+				token_hash = $2,
+				refresh_token_hash = $3,
+				expires_at = NOW() + ('1 minute'::interval * $4),
+				refresh_expires_at = NOW() + ('1 hour'::interval * $5),
+				last_used_at = NOW(),
+				ip = COALESCE($6, ip)
+			WHERE refresh_token_hash = $1 AND refresh_expires_at > NOW()
+			RETURNING id, user_id, created_at, last_used_at, client_name, client_version, ip"#,
+			refresh_token_hash,
+			new_access_token_hash,
+			new_refresh_token_hash,
+			access_lifetime_minutes as i64,
+			lifetime_hours as i64,
+			ip
+		)
+		.fetch_optional(transaction.as_executor())
+		.await?;
+
+		let session = match session {
+			Some(x) => x,
+			None => {
+				let reused_session = sqlx::query_scalar!(
+					r#"SELECT session_id FROM used_refresh_tokens WHERE token_hash = $1"#,
+					refresh_token_hash
+				)
+				.fetch_optional(transaction.as_executor())
+				.await?;
+
+				return match reused_session {
+					Some(session_id) => {
+						// connections of the session are closed through the user events
+						sqlx::query!(r#"DELETE FROM active_sessions WHERE id = $1"#, session_id)
+							.execute(transaction.as_executor())
+							.await?;
+						transaction.commit().await?;
+
+						Ok(Err(RefreshError::Reused))
+					}
+					None => Ok(Err(RefreshError::Invalid)),
+				};
+			}
+		};
+
+		sqlx::query!(
+			r#"INSERT INTO used_refresh_tokens (token_hash, session_id) VALUES ($1, $2)"#,
+			refresh_token_hash,
+			session.id
+		)
+		.execute(transaction.as_executor())
+		.await?;
+
+		transaction.commit().await?;
+
+		Ok(Ok(session))
+	}
+	/// Pushes the expiry of the session `lifetime_hours` into the future, if it would expire sooner.
+	/// For refreshable sessions this is the expiry of the refresh token
+	pub async fn extend_active_session(
+		&mut self,
+		session_id: Uuid,
+		lifetime_hours: u32,
+	) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"UPDATE active_sessions
+			SET
+				expires_at = CASE
+					WHEN refresh_expires_at IS NULL
+					THEN GREATEST(expires_at, NOW() + ('1 hour'::interval * $2))
+					ELSE expires_at
+				END,
+				refresh_expires_at = GREATEST(refresh_expires_at, NOW() + ('1 hour'::interval * $2))
+			WHERE id = $1 AND COALESCE(refresh_expires_at, expires_at) > NOW()"#,
+			session_id,
 			lifetime_hours as i64
 		)
 		.execute(self.as_executor())
 		.await
-		.map(|_| session_token)
+		.map(|_| ())
+	}
+	/// Returns the session of the token and marks it as used from `ip`,
+	/// `None` if the token is invalid or expired
+	pub async fn use_active_session(
+		&mut self,
+		token_hash: &str,
+		ip: Option<&str>,
+	) -> sqlx::Result<Option<ActiveSession>> {
+		sqlx::query_as!(
+			ActiveSession,
+			r#"UPDATE active_sessions
+			SET last_used_at = NOW(), ip = COALESCE($2, ip)
+			WHERE token_hash = $1 AND expires_at > NOW()
+			RETURNING id, user_id, created_at, last_used_at, client_name, client_version, ip"#,
+			token_hash,
+			ip
+		)
+		.fetch_optional(self.as_executor())
+		.await
+	}
+	pub async fn is_session_active(&mut self, session_id: Uuid) -> sqlx::Result<bool> {
+		sqlx::query_scalar!(
+			r#"SELECT EXISTS(
+				SELECT 1 FROM active_sessions
+				WHERE id = $1 AND COALESCE(refresh_expires_at, expires_at) > NOW()
+			) AS "exists!""#,
+			session_id
+		)
+		.fetch_one(self.as_executor())
+		.await
+	}
+	pub async fn user_active_sessions(
+		&mut self,
+		user_id: Uuid,
+	) -> sqlx::Result<Vec<ActiveSession>> {
+		sqlx::query_as!(
+			ActiveSession,
+			r#"SELECT id, user_id, created_at, last_used_at, client_name, client_version, ip
+			FROM active_sessions
+			WHERE user_id = $1 AND COALESCE(refresh_expires_at, expires_at) > NOW()
+			ORDER BY last_used_at DESC"#,
+			user_id
+		)
+		.fetch_all(self.as_executor())
+		.await
+	}
+	/// Returns `false` if the user has no such session
+	pub async fn delete_user_session(
+		&mut self,
+		user_id: Uuid,
+		session_id: Uuid,
+	) -> sqlx::Result<bool> {
+		sqlx::query!(
+			r#"DELETE FROM active_sessions WHERE user_id = $1 AND id = $2"#,
+			user_id,
+			session_id
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|r| r.rows_affected() == 1)
+	}
+	/// Logs the user out everywhere except for the `keep` session
+	pub async fn delete_other_user_sessions(
+		&mut self,
+		user_id: Uuid,
+		keep: Uuid,
+	) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"DELETE FROM active_sessions WHERE user_id = $1 AND id != $2"#,
+			user_id,
+			keep
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+	/// Logs the user out everywhere
+	pub async fn delete_user_sessions(&mut self, user_id: Uuid) -> sqlx::Result<()> {
+		sqlx::query!(r#"DELETE FROM active_sessions WHERE user_id = $1"#, user_id)
+			.execute(self.as_executor())
+			.await
+			.map(|_| ())
 	}
 }
@@ -0,0 +1,129 @@
+use super::{Database, ExecutorHack};
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Clone, Debug)]
+pub struct ChatroomInvite {
+	pub code: String,
+	pub chatroom: Uuid,
+	pub chatroom_name: String,
+	pub expires_at: Option<DateTime<Utc>>,
+	pub max_uses: Option<i32>,
+	pub uses: i32,
+}
+
+#[derive(Debug, Error)]
+pub enum RedeemInviteError {
+	#[error("invite doesnt exist, expired or was used up")]
+	Invalid,
+	#[error("banned from the chatroom")]
+	Banned,
+}
+
+impl<D: ExecutorHack> Database<D> {
+	pub async fn insert_chatroom_invite(
+		&mut self,
+		code: &str,
+		chatroom_id: Uuid,
+		created_by: Uuid,
+		expires_at: Option<DateTime<Utc>>,
+		max_uses: Option<i32>,
+	) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"INSERT INTO chatroom_invites (code, chatroom, created_by, expires_at, max_uses)
+			VALUES ($1, $2, $3, $4, $5)"#,
+			code,
+			chatroom_id,
+			created_by,
+			expires_at,
+			max_uses
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+	/// Returns `None` if the invite doesnt exist, expired or was used up
+	pub async fn valid_chatroom_invite(
+		&mut self,
+		code: &str,
+	) -> sqlx::Result<Option<ChatroomInvite>> {
+		sqlx::query_as!(
+			ChatroomInvite,
+			r#"SELECT i.code, i.chatroom, c.name AS chatroom_name, i.expires_at, i.max_uses, i.uses
+			FROM chatroom_invites i
+			JOIN chatrooms c ON c.id = i.chatroom
+			WHERE
+				i.code = $1
+			AND
+				(i.expires_at IS NULL OR i.expires_at > NOW())
+			AND
+				(i.max_uses IS NULL OR i.uses < i.max_uses)"#,
+			code
+		)
+		.fetch_optional(self.as_executor())
+		.await
+	}
+	/// Returns `false` if the invite doesnt exist
+	pub async fn delete_chatroom_invite(&mut self, code: &str) -> sqlx::Result<bool> {
+		sqlx::query!(r#"DELETE FROM chatroom_invites WHERE code = $1"#, code)
+			.execute(self.as_executor())
+			.await
+			.map(|r| r.rows_affected() == 1)
+	}
+	/// Adds the user to the chatroom of the invite and returns the chatroom.
+	///
+	/// Users that are already members dont use up the invite.
+	pub async fn redeem_chatroom_invite(
+		&mut self,
+		code: &str,
+		user_id: Uuid,
+	) -> sqlx::Result<Result<Uuid, RedeemInviteError>> {
+		let mut transaction = self.transaction().await?;
+
+		// locks the invite, so concurrent redeems cant go over max_uses
+		let chatroom = sqlx::query_scalar!(
+			r#"SELECT chatroom
+			FROM chatroom_invites
+			WHERE
+				code = $1
+			AND
+				(expires_at IS NULL OR expires_at > NOW())
+			AND
+				(max_uses IS NULL OR uses < max_uses)
+			FOR UPDATE"#,
+			code
+		)
+		.fetch_optional(transaction.as_executor())
+		.await?;
+
+		let chatroom = match chatroom {
+			Some(x) => x,
+			None => return Ok(Err(RedeemInviteError::Invalid)),
+		};
+
+		if transaction.is_chatroom_member(chatroom, user_id).await? {
+			return Ok(Ok(chatroom));
+		}
+
+		if transaction
+			.is_banned_from_chatroom(chatroom, user_id)
+			.await?
+		{
+			return Ok(Err(RedeemInviteError::Banned));
+		}
+
+		sqlx::query!(
+			r#"UPDATE chatroom_invites SET uses = uses + 1 WHERE code = $1"#,
+			code
+		)
+		.execute(transaction.as_executor())
+		.await?;
+
+		transaction.add_chatroom_member(chatroom, user_id).await?;
+
+		transaction.commit().await?;
+
+		Ok(Ok(chatroom))
+	}
+}
@@ -0,0 +1,177 @@
+use super::{Database, ExecutorHack};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+
+/// Ordered by privilege
+#[derive(sqlx::Type, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[sqlx(type_name = "chatroom_role", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ChatroomRole {
+	Member,
+	Moderator,
+	Owner,
+}
+
+#[derive(Clone, Debug)]
+pub struct ChatroomMember {
+	pub user_id: Uuid,
+	pub username: String,
+	pub joined_at: DateTime<Utc>,
+	pub role: ChatroomRole,
+	pub muted_until: Option<DateTime<Utc>>,
+}
+
+impl ChatroomMember {
+	pub fn is_muted(&self) -> bool {
+		self.muted_until.is_some_and(|until| until > Utc::now())
+	}
+}
+
+impl<D: ExecutorHack> Database<D> {
+	/// Does nothing if the user is already a member
+	pub async fn add_chatroom_member(
+		&mut self,
+		chatroom_id: Uuid,
+		user_id: Uuid,
+	) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"INSERT INTO chatroom_members (chatroom, user_id)
+			VALUES ($1, $2)
+			ON CONFLICT (chatroom, user_id) DO NOTHING"#,
+			chatroom_id,
+			user_id
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+	pub async fn remove_chatroom_member(
+		&mut self,
+		chatroom_id: Uuid,
+		user_id: Uuid,
+	) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"DELETE FROM chatroom_members
+			WHERE chatroom = $1 AND user_id = $2"#,
+			chatroom_id,
+			user_id
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+	pub async fn chatroom_members(
+		&mut self,
+		chatroom_id: Uuid,
+	) -> sqlx::Result<Vec<ChatroomMember>> {
+		sqlx::query_as!(
+			ChatroomMember,
+			r#"SELECT
+				m.user_id,
+				u.username,
+				m.joined_at,
+				m.role AS "role: ChatroomRole",
+				m.muted_until
+			FROM chatroom_members m
+			JOIN users u ON u.id = m.user_id
+			WHERE m.chatroom = $1
+			ORDER BY m.joined_at ASC"#,
+			chatroom_id
+		)
+		.fetch_all(self.as_executor())
+		.await
+	}
+	pub async fn chatroom_member(
+		&mut self,
+		chatroom_id: Uuid,
+		user_id: Uuid,
+	) -> sqlx::Result<Option<ChatroomMember>> {
+		sqlx::query_as!(
+			ChatroomMember,
+			r#"SELECT
+				m.user_id,
+				u.username,
+				m.joined_at,
+				m.role AS "role: ChatroomRole",
+				m.muted_until
+			FROM chatroom_members m
+			JOIN users u ON u.id = m.user_id
+			WHERE m.chatroom = $1 AND m.user_id = $2"#,
+			chatroom_id,
+			user_id
+		)
+		.fetch_optional(self.as_executor())
+		.await
+	}
+	pub async fn set_chatroom_member_role(
+		&mut self,
+		chatroom_id: Uuid,
+		user_id: Uuid,
+		role: ChatroomRole,
+	) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"UPDATE chatroom_members
+			SET role = $3
+			WHERE chatroom = $1 AND user_id = $2"#,
+			chatroom_id,
+			user_id,
+			role as ChatroomRole
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+	/// `None` unmutes the member
+	pub async fn set_chatroom_member_muted_until(
+		&mut self,
+		chatroom_id: Uuid,
+		user_id: Uuid,
+		muted_until: Option<DateTime<Utc>>,
+	) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"UPDATE chatroom_members
+			SET muted_until = $3
+			WHERE chatroom = $1 AND user_id = $2"#,
+			chatroom_id,
+			user_id,
+			muted_until
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+	pub async fn is_chatroom_member(
+		&mut self,
+		chatroom_id: Uuid,
+		user_id: Uuid,
+	) -> sqlx::Result<bool> {
+		sqlx::query_scalar!(
+			r#"SELECT EXISTS(
+				SELECT 1 FROM chatroom_members
+				WHERE chatroom = $1 AND user_id = $2
+			) AS "exists!""#,
+			chatroom_id,
+			user_id
+		)
+		.fetch_one(self.as_executor())
+		.await
+	}
+	/// Returns (chatroom, user) for all other members of the chatrooms of the user
+	pub async fn chatroom_co_members(&mut self, user_id: Uuid) -> sqlx::Result<Vec<(Uuid, Uuid)>> {
+		sqlx::query!(
+			r#"SELECT theirs.chatroom, theirs.user_id
+			FROM chatroom_members mine
+			JOIN chatroom_members theirs ON mine.chatroom = theirs.chatroom
+			WHERE mine.user_id = $1 AND theirs.user_id != $1"#,
+			user_id
+		)
+		.fetch_all(self.as_executor())
+		.await
+		.map(|rows| {
+			rows.into_iter()
+				.map(|row| (row.chatroom, row.user_id))
+				.collect()
+		})
+	}
+}
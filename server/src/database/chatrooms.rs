@@ -0,0 +1,109 @@
+use super::{Database, ExecutorHack, chatroom_members::ChatroomRole};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+#[derive(Clone, Debug)]
+pub struct Chatroom {
+	pub id: Uuid,
+	pub name: String,
+	/// `None` for direct chats, or if the user was deleted
+	pub created_by: Option<Uuid>,
+	pub created_at: DateTime<Utc>,
+	pub archived_at: Option<DateTime<Utc>>,
+}
+
+/// A chatroom as seen in the chatroom list of a member
+#[derive(Clone, Debug)]
+pub struct UserChatroom {
+	pub id: Uuid,
+	pub name: String,
+	pub archived: bool,
+	/// `None` if there are no messages yet
+	pub last_seq_id: Option<i64>,
+}
+
+impl<D: ExecutorHack> Database<D> {
+	pub async fn chatroom_by_id(&mut self, id: Uuid) -> sqlx::Result<Option<Chatroom>> {
+		sqlx::query_as!(
+			Chatroom,
+			r#"SELECT id, name, created_by, created_at, archived_at
+			FROM chatrooms
+			WHERE id = $1"#,
+			id
+		)
+		.fetch_optional(self.as_executor())
+		.await
+	}
+	/// Creates the chatroom with the creator as its only member and owner
+	pub async fn create_chatroom(&mut self, name: &str, creator: Uuid) -> sqlx::Result<Uuid> {
+		let id = Uuid::now_v7();
+
+		let mut transaction = self.transaction().await?;
+
+		sqlx::query!(
+			r#"INSERT INTO chatrooms (id, name, created_by)
+			VALUES ($1, $2, $3)"#,
+			id,
+			name,
+			creator
+		)
+		.execute(transaction.as_executor())
+		.await?;
+
+		transaction.add_chatroom_member(id, creator).await?;
+		transaction
+			.set_chatroom_member_role(id, creator, ChatroomRole::Owner)
+			.await?;
+
+		transaction.commit().await?;
+
+		Ok(id)
+	}
+	pub async fn rename_chatroom(&mut self, id: Uuid, name: &str) -> sqlx::Result<()> {
+		sqlx::query!(r#"UPDATE chatrooms SET name = $2 WHERE id = $1"#, id, name)
+			.execute(self.as_executor())
+			.await
+			.map(|_| ())
+	}
+	pub async fn set_chatroom_archived(&mut self, id: Uuid, archived: bool) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"UPDATE chatrooms
+			SET archived_at = CASE WHEN $2 THEN COALESCE(archived_at, NOW()) END
+			WHERE id = $1"#,
+			id,
+			archived
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+	/// Deletes the chatroom with all of its messages
+	pub async fn delete_chatroom(&mut self, id: Uuid) -> sqlx::Result<()> {
+		sqlx::query!(r#"DELETE FROM chatrooms WHERE id = $1"#, id)
+			.execute(self.as_executor())
+			.await
+			.map(|_| ())
+	}
+	/// Returns the chatrooms the user is a member of, excluding direct chats
+	pub async fn user_group_chatrooms(&mut self, user_id: Uuid) -> sqlx::Result<Vec<UserChatroom>> {
+		sqlx::query_as!(
+			UserChatroom,
+			r#"SELECT
+				c.id,
+				c.name,
+				c.archived_at IS NOT NULL AS "archived!",
+				s.next_sequence_id - 1 AS "last_seq_id?"
+			FROM chatroom_members m
+			JOIN chatrooms c ON c.id = m.chatroom
+			LEFT JOIN messages_sequential_ids s ON s.chatroom = c.id
+			WHERE
+				m.user_id = $1
+			AND
+				NOT EXISTS(SELECT 1 FROM direct_chats d WHERE d.chatroom = c.id)
+			ORDER BY m.joined_at ASC"#,
+			user_id
+		)
+		.fetch_all(self.as_executor())
+		.await
+	}
+}
@@ -0,0 +1,44 @@
+use super::{Database, ExecutorHack};
+use uuid::Uuid;
+
+#[derive(Clone, Debug)]
+pub struct DirectChat {
+	pub chatroom: Uuid,
+	/// the other user, `None` if they deleted their account
+	pub user_id: Option<Uuid>,
+	pub username: Option<String>,
+	/// `None` if there are no messages yet
+	pub last_seq_id: Option<i64>,
+}
+
+impl<D: ExecutorHack> Database<D> {
+	/// Returns the chatroom of the direct chat between the two users,
+	/// creating it if it doesnt exist yet
+	pub async fn open_direct_chat(&mut self, user_1: Uuid, user_2: Uuid) -> sqlx::Result<Uuid> {
+		sqlx::query_scalar!(
+			r#"SELECT open_direct_chat($1, $2, $3) AS "chatroom!""#,
+			Uuid::now_v7(),
+			user_1,
+			user_2
+		)
+		.fetch_one(self.as_executor())
+		.await
+	}
+	pub async fn user_direct_chats(&mut self, user_id: Uuid) -> sqlx::Result<Vec<DirectChat>> {
+		sqlx::query_as!(
+			DirectChat,
+			r#"SELECT
+				d.chatroom AS "chatroom!",
+				u.id AS "user_id?",
+				u.username AS "username?",
+				s.next_sequence_id - 1 AS "last_seq_id?"
+			FROM direct_chats d
+			LEFT JOIN users u ON u.id = (CASE WHEN d.user_a = $1 THEN d.user_b ELSE d.user_a END)
+			LEFT JOIN messages_sequential_ids s ON s.chatroom = d.chatroom
+			WHERE d.user_a = $1 OR d.user_b = $1"#,
+			user_id
+		)
+		.fetch_all(self.as_executor())
+		.await
+	}
+}
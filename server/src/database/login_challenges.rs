@@ -0,0 +1,67 @@
+use super::{Database, ExecutorHack};
+use uuid::Uuid;
+
+const MAX_CHALLENGE_ATTEMPTS: i16 = 5;
+
+/// A login waiting for the second factor
+#[derive(Clone, Debug)]
+pub struct LoginChallenge {
+	pub user_id: Uuid,
+	pub client_name: Option<String>,
+	pub client_version: Option<String>,
+	/// Password hash of the password reset that started the login
+	pub new_password: Option<String>,
+}
+
+impl<D: ExecutorHack> Database<D> {
+	pub async fn insert_login_challenge(
+		&mut self,
+		token_hash: &str,
+		user_id: Uuid,
+		lifetime_minutes: u32,
+		client_name: Option<&str>,
+		client_version: Option<&str>,
+		new_password: Option<&str>,
+	) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"INSERT INTO login_challenges (token_hash, user_id, expires_at, client_name, client_version, new_password)
+			VALUES ($1, $2, NOW() + ('1 min'::interval * $3), $4, $5, $6)"#,
+			token_hash,
+			user_id,
+			lifetime_minutes as i64,
+			client_name,
+			client_version,
+			new_password
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+	/// Counts an attempt to complete the challenge, `None` if it doesnt exist, expired
+	/// or was attempted too many times
+	pub async fn attempt_login_challenge(
+		&mut self,
+		token_hash: &str,
+	) -> sqlx::Result<Option<LoginChallenge>> {
+		sqlx::query_as!(
+			LoginChallenge,
+			r#"UPDATE login_challenges
+			SET attempts = attempts + 1
+			WHERE token_hash = $1 AND expires_at > NOW() AND attempts < $2
+			RETURNING user_id, client_name, client_version, new_password"#,
+			token_hash,
+			MAX_CHALLENGE_ATTEMPTS
+		)
+		.fetch_optional(self.as_executor())
+		.await
+	}
+	pub async fn delete_login_challenge(&mut self, token_hash: &str) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"DELETE FROM login_challenges WHERE token_hash = $1"#,
+			token_hash
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+}
@@ -12,16 +12,27 @@ pub struct Message {
 	pub id: Uuid,
 	pub chatroom: Uuid,
 	pub sequence_id: i64,
-	pub user_id: Uuid,
+	/// `None` if the author deleted their account
+	pub user_id: Option<Uuid>,
+	/// Empty if the message was deleted
 	pub message: String,
 	pub sent_at: DateTime<Local>,
+	pub edited_at: Option<DateTime<Local>>,
+	pub deleted_at: Option<DateTime<Local>>,
+	pub reply_to: Option<Uuid>,
+	pub thread_root: Option<Uuid>,
+	/// Id of the last edit, deletion or reaction change in the chatroom that changed this message,
+	/// `None` if it was never changed
+	pub change_id: Option<i64>,
 }
 
 impl<D: ExecutorHack> Database<D> {
 	pub async fn message_by_id(&mut self, id: Uuid) -> sqlx::Result<Option<Message>> {
 		sqlx::query_as!(
 			Message,
-			r#"SELECT id, chatroom, sequence_id, user_id, message, sent_at
+			r#"SELECT id, chatroom, sequence_id, user_id, message, sent_at,
+				edited_at AS "edited_at: DateTime<Local>", deleted_at AS "deleted_at: DateTime<Local>",
+				reply_to, thread_root, change_id
 			FROM messages
 			WHERE id = $1"#,
 			id,
@@ -47,7 +58,9 @@ impl<D: ExecutorHack> Database<D> {
 
 		sqlx::query_as!(
 			Message,
-			r#"SELECT id, chatroom, sequence_id, user_id, message, sent_at
+			r#"SELECT id, chatroom, sequence_id, user_id, message, sent_at,
+				edited_at AS "edited_at: DateTime<Local>", deleted_at AS "deleted_at: DateTime<Local>",
+				reply_to, thread_root, change_id
 			FROM messages
 			WHERE
 				chatroom = $3
@@ -62,25 +75,184 @@ impl<D: ExecutorHack> Database<D> {
 		)
 		.fetch(self.as_executor())
 	}
+	/// Returns up to `limit` (or all if `None`) messages with the latest changes after `after_change_id`,
+	/// ordered by change id ascending
+	pub async fn messages_changed_after(
+		&mut self,
+		chatroom_id: &Uuid,
+		after_change_id: i64,
+		limit: Option<i64>,
+	) -> sqlx::Result<Vec<Message>> {
+		let mut messages = sqlx::query_as!(
+			Message,
+			r#"SELECT id, chatroom, sequence_id, user_id, message, sent_at,
+				edited_at AS "edited_at: DateTime<Local>", deleted_at AS "deleted_at: DateTime<Local>",
+				reply_to, thread_root, change_id
+			FROM messages
+			WHERE
+				chatroom = $1
+			AND
+				change_id > $2
+			ORDER BY change_id DESC
+			LIMIT $3"#,
+			chatroom_id,
+			after_change_id,
+			limit
+		)
+		.fetch_all(self.as_executor())
+		.await?;
+
+		messages.reverse();
+
+		Ok(messages)
+	}
+	/// Returns up to `limit` latest messages with sequence ids lower than `before_seq_id`,
+	/// ordered by sequence id ascending
+	pub async fn messages_before_seq_id(
+		&mut self,
+		chatroom_id: &Uuid,
+		before_seq_id: Option<i64>,
+		limit: u32,
+	) -> sqlx::Result<Vec<Message>> {
+		let mut messages = sqlx::query_as!(
+			Message,
+			r#"SELECT id, chatroom, sequence_id, user_id, message, sent_at,
+				edited_at AS "edited_at: DateTime<Local>", deleted_at AS "deleted_at: DateTime<Local>",
+				reply_to, thread_root, change_id
+			FROM messages
+			WHERE
+				chatroom = $1
+			AND
+				($2::BIGINT IS NULL OR sequence_id < $2)
+			ORDER BY sequence_id DESC
+			LIMIT $3"#,
+			chatroom_id,
+			before_seq_id,
+			limit as i64
+		)
+		.fetch_all(self.as_executor())
+		.await?;
+
+		messages.reverse();
+
+		Ok(messages)
+	}
+	/// Returns up to `limit` latest messages in the thread of `thread_root` with sequence ids
+	/// lower than `before_seq_id`, ordered by sequence id ascending. The root itself is not included
+	pub async fn thread_messages_before_seq_id(
+		&mut self,
+		thread_root: &Uuid,
+		before_seq_id: Option<i64>,
+		limit: u32,
+	) -> sqlx::Result<Vec<Message>> {
+		let mut messages = sqlx::query_as!(
+			Message,
+			r#"SELECT id, chatroom, sequence_id, user_id, message, sent_at,
+				edited_at AS "edited_at: DateTime<Local>", deleted_at AS "deleted_at: DateTime<Local>",
+				reply_to, thread_root, change_id
+			FROM messages
+			WHERE
+				thread_root = $1
+			AND
+				($2::BIGINT IS NULL OR sequence_id < $2)
+			ORDER BY sequence_id DESC
+			LIMIT $3"#,
+			thread_root,
+			before_seq_id,
+			limit as i64
+		)
+		.fetch_all(self.as_executor())
+		.await?;
+
+		messages.reverse();
+
+		Ok(messages)
+	}
 	/// Returns (message uuid, sequential id)
+	///
+	/// If the user already sent a message with the same nonce in the chatroom, nothing is inserted
+	/// and the ids of the existing message are returned instead
 	pub async fn insert_message(
 		&mut self,
 		chatroom_id: Uuid,
 		user_id: Uuid,
 		message: &str,
+		nonce: Uuid,
+		reply_to: Option<Uuid>,
 	) -> sqlx::Result<(Uuid, i64)> {
 		let msg_id = Uuid::now_v7();
 
-		sqlx::query_scalar!(
-			r#"SELECT add_message($1, $2, $3, $4)"#,
+		sqlx::query!(
+			r#"SELECT message_id AS "message_id!", message_sequence_id AS "message_sequence_id!"
+			FROM add_message($1, $2, $3, $4, $5, $6)"#,
 			msg_id,
 			chatroom_id,
 			user_id,
-			message
+			message,
+			nonce,
+			reply_to
 		)
 		.fetch_one(self.as_executor())
 		.await
-		.map(|seq_id| (msg_id, seq_id.unwrap()))
+		.map(|row| (row.message_id, row.message_sequence_id))
+	}
+	/// Returns (message uuid, sequential id) of the message the user sent with the nonce in the chatroom
+	pub async fn message_by_nonce(
+		&mut self,
+		chatroom_id: Uuid,
+		user_id: Uuid,
+		nonce: Uuid,
+	) -> sqlx::Result<Option<(Uuid, i64)>> {
+		sqlx::query!(
+			r#"SELECT id, sequence_id
+			FROM messages
+			WHERE chatroom = $1 AND user_id = $2 AND nonce = $3"#,
+			chatroom_id,
+			user_id,
+			nonce
+		)
+		.fetch_optional(self.as_executor())
+		.await
+		.map(|row| row.map(|row| (row.id, row.sequence_id)))
+	}
+	/// Returns `false` if the message doesnt exist or was deleted
+	pub async fn edit_message(&mut self, id: Uuid, message: &str) -> sqlx::Result<bool> {
+		sqlx::query!(
+			r#"UPDATE messages
+			SET message = $2, edited_at = NOW()
+			WHERE id = $1 AND deleted_at IS NULL"#,
+			id,
+			message
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|result| result.rows_affected() == 1)
+	}
+	/// Removes the content of the message, leaving a tombstone in its place.
+	///
+	/// Returns `false` if the message doesnt exist or was already deleted
+	pub async fn delete_message(&mut self, id: Uuid) -> sqlx::Result<bool> {
+		sqlx::query!(
+			r#"UPDATE messages
+			SET message = '', deleted_at = NOW()
+			WHERE id = $1 AND deleted_at IS NULL"#,
+			id
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|result| result.rows_affected() == 1)
+	}
+	/// Returns -1 if no message in the chatroom was changed yet
+	pub async fn fetch_last_message_change_id(&mut self, chatroom_id: &Uuid) -> sqlx::Result<i64> {
+		sqlx::query_scalar!(
+			r#"SELECT next_change_id - 1 AS "last_change_id!"
+			FROM messages_sequential_ids
+			WHERE chatroom = $1"#,
+			chatroom_id
+		)
+		.fetch_optional(self.as_executor())
+		.await
+		.map(|opt| opt.unwrap_or(-1))
 	}
 	pub async fn fetch_last_message_seq_id(&mut self, chatroom_id: &Uuid) -> sqlx::Result<i64> {
 		sqlx::query_scalar!(
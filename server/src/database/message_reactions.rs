@@ -0,0 +1,76 @@
+use super::{Database, ExecutorHack};
+use uuid::Uuid;
+
+#[derive(Clone, Debug)]
+pub struct ReactionCount {
+	pub message_id: Uuid,
+	pub emoji: String,
+	pub count: i64,
+	/// whether the viewing user is one of the reactors
+	pub reacted: bool,
+}
+
+impl<D: ExecutorHack> Database<D> {
+	/// Does nothing if the user already reacted with the same emoji
+	pub async fn add_reaction(
+		&mut self,
+		message_id: Uuid,
+		user_id: Uuid,
+		emoji: &str,
+	) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"INSERT INTO message_reactions (message_id, user_id, emoji)
+			VALUES ($1, $2, $3)
+			ON CONFLICT (message_id, user_id, emoji) DO NOTHING"#,
+			message_id,
+			user_id,
+			emoji
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+	pub async fn remove_reaction(
+		&mut self,
+		message_id: Uuid,
+		user_id: Uuid,
+		emoji: &str,
+	) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"DELETE FROM message_reactions
+			WHERE message_id = $1 AND user_id = $2 AND emoji = $3"#,
+			message_id,
+			user_id,
+			emoji
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+	/// Returns the reaction counts of the messages, ordered by the first reaction with each emoji.
+	///
+	/// Deleted messages have no reactions.
+	pub async fn reaction_counts(
+		&mut self,
+		message_ids: &[Uuid],
+		viewer_id: Uuid,
+	) -> sqlx::Result<Vec<ReactionCount>> {
+		sqlx::query_as!(
+			ReactionCount,
+			r#"SELECT
+				r.message_id,
+				r.emoji,
+				count(*) AS "count!",
+				bool_or(r.user_id = $2) AS "reacted!"
+			FROM message_reactions r
+			JOIN messages m ON m.id = r.message_id
+			WHERE r.message_id = ANY($1) AND m.deleted_at IS NULL
+			GROUP BY r.message_id, r.emoji
+			ORDER BY min(r.reacted_at) ASC"#,
+			message_ids,
+			viewer_id
+		)
+		.fetch_all(self.as_executor())
+		.await
+	}
+}
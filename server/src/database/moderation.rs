@@ -0,0 +1,113 @@
+use super::{Database, ExecutorHack, chatroom_members::ChatroomRole};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// An entry of the moderation log
+#[derive(Clone, Copy, Debug)]
+pub enum ModerationAction {
+	Kick,
+	Ban,
+	Unban,
+	Mute { until: DateTime<Utc> },
+	Unmute,
+	SetRole(ChatroomRole),
+}
+
+pub struct UserNotFound;
+
+impl<D: ExecutorHack> Database<D> {
+	/// Does nothing if the user is already banned
+	pub async fn ban_from_chatroom(
+		&mut self,
+		chatroom_id: Uuid,
+		user_id: Uuid,
+		banned_by: Uuid,
+	) -> sqlx::Result<Result<(), UserNotFound>> {
+		match sqlx::query!(
+			r#"INSERT INTO chatroom_bans (chatroom, user_id, banned_by)
+			VALUES ($1, $2, $3)
+			ON CONFLICT (chatroom, user_id) DO NOTHING"#,
+			chatroom_id,
+			user_id,
+			banned_by
+		)
+		.execute(self.as_executor())
+		.await
+		{
+			Ok(_) => Ok(Ok(())),
+			Err(sqlx::Error::Database(db_err)) => {
+				if db_err.is_foreign_key_violation()
+					&& db_err.constraint() == Some("chatroom_bans_user_id_fkey")
+				{
+					Ok(Err(UserNotFound))
+				} else {
+					Err(sqlx::Error::Database(db_err))
+				}
+			}
+			Err(e) => Err(e),
+		}
+	}
+	/// Returns `false` if the user wasnt banned
+	pub async fn unban_from_chatroom(
+		&mut self,
+		chatroom_id: Uuid,
+		user_id: Uuid,
+	) -> sqlx::Result<bool> {
+		sqlx::query!(
+			r#"DELETE FROM chatroom_bans
+			WHERE chatroom = $1 AND user_id = $2"#,
+			chatroom_id,
+			user_id
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|r| r.rows_affected() == 1)
+	}
+	pub async fn is_banned_from_chatroom(
+		&mut self,
+		chatroom_id: Uuid,
+		user_id: Uuid,
+	) -> sqlx::Result<bool> {
+		sqlx::query_scalar!(
+			r#"SELECT EXISTS(
+				SELECT 1 FROM chatroom_bans
+				WHERE chatroom = $1 AND user_id = $2
+			) AS "exists!""#,
+			chatroom_id,
+			user_id
+		)
+		.fetch_one(self.as_executor())
+		.await
+	}
+	pub async fn log_moderation_action(
+		&mut self,
+		chatroom_id: Uuid,
+		actor: Uuid,
+		target: Uuid,
+		action: ModerationAction,
+	) -> sqlx::Result<()> {
+		let (action, muted_until, role) = match action {
+			ModerationAction::Kick => ("kick", None, None),
+			ModerationAction::Ban => ("ban", None, None),
+			ModerationAction::Unban => ("unban", None, None),
+			ModerationAction::Mute { until } => ("mute", Some(until), None),
+			ModerationAction::Unmute => ("unmute", None, None),
+			ModerationAction::SetRole(role) => ("set_role", None, Some(role)),
+		};
+
+		sqlx::query!(
+			r#"INSERT INTO moderation_log (id, chatroom, actor, target, action, muted_until, role)
+			VALUES ($1, $2, $3, $4, $5::TEXT::moderation_action, $6, $7)"#,
+			Uuid::now_v7(),
+			chatroom_id,
+			actor,
+			target,
+			action,
+			muted_until,
+			role as Option<ChatroomRole>
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+}
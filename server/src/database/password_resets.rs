@@ -0,0 +1,68 @@
+use super::{Database, ExecutorHack};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Debug, Error)]
+#[error("a password reset is already pending for the user")]
+pub struct PasswordResetPending;
+
+impl<D: ExecutorHack> Database<D> {
+	/// Replaces the pending reset of the user only if it expired
+	pub async fn insert_password_reset(
+		&mut self,
+		user_id: Uuid,
+		token_hash: &str,
+		lifetime_minutes: u32,
+	) -> sqlx::Result<Result<(), PasswordResetPending>> {
+		sqlx::query!(
+			r#"
+			INSERT INTO password_resets (user_id, token_hash, expires_at)
+			VALUES ($1, $2, NOW() + ('1 min'::interval * $3))
+			ON CONFLICT (user_id)
+			DO UPDATE SET
+			    token_hash = $2,
+			    expires_at = NOW() + ('1 min'::interval * $3)
+			WHERE
+			    password_resets.expires_at < NOW()"#,
+			user_id,
+			token_hash,
+			lifetime_minutes as i64
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|r| {
+			if r.rows_affected() == 1 {
+				Ok(())
+			} else {
+				Err(PasswordResetPending)
+			}
+		})
+	}
+	/// Removes the reset and returns its user, `None` if it doesnt exist or expired
+	pub async fn take_password_reset(&mut self, token_hash: &str) -> sqlx::Result<Option<Uuid>> {
+		sqlx::query_scalar!(
+			r#"DELETE FROM password_resets
+			WHERE token_hash = $1 AND expires_at > NOW()
+			RETURNING user_id"#,
+			token_hash
+		)
+		.fetch_optional(self.as_executor())
+		.await
+	}
+	/// Sets the new password and logs the user out everywhere,
+	/// since whoever knew the old password shouldnt stay logged in
+	pub async fn apply_password_reset(
+		&mut self,
+		user_id: Uuid,
+		password_hash: &str,
+	) -> sqlx::Result<()> {
+		let mut transaction = self.transaction().await?;
+
+		transaction
+			.set_user_password(user_id, password_hash)
+			.await?;
+		transaction.delete_user_sessions(user_id).await?;
+
+		transaction.commit().await
+	}
+}
@@ -0,0 +1,88 @@
+use super::{Database, ExecutorHack};
+use serde::Deserialize;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(sqlx::Type, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[sqlx(type_name = "presence", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Presence {
+	Online,
+	Away,
+	Offline,
+}
+
+impl<D: ExecutorHack> Database<D> {
+	/// Inserts the socket, or extends its lifetime if it already exists
+	pub async fn upsert_presence_socket(
+		&mut self,
+		socket_id: Uuid,
+		user_id: Uuid,
+		away: bool,
+		lifetime: Duration,
+	) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"INSERT INTO presence_sockets (id, user_id, away, expires_at)
+			VALUES ($1, $2, $3, NOW() + ('1 sec'::interval * $4))
+			ON CONFLICT (id) DO UPDATE
+			SET away = EXCLUDED.away, expires_at = EXCLUDED.expires_at"#,
+			socket_id,
+			user_id,
+			away,
+			lifetime.as_secs_f64()
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+	pub async fn delete_presence_socket(&mut self, socket_id: Uuid) -> sqlx::Result<()> {
+		sqlx::query!(r#"DELETE FROM presence_sockets WHERE id = $1"#, socket_id)
+			.execute(self.as_executor())
+			.await
+			.map(|_| ())
+	}
+	pub async fn delete_expired_presence_sockets(&mut self) -> sqlx::Result<()> {
+		sqlx::query!(r#"DELETE FROM presence_sockets WHERE expires_at < NOW()"#)
+			.execute(self.as_executor())
+			.await
+			.map(|_| ())
+	}
+	pub async fn user_presence(&mut self, user_id: Uuid) -> sqlx::Result<Presence> {
+		sqlx::query_scalar!(
+			r#"SELECT user_presence($1) AS "presence!: Presence""#,
+			user_id
+		)
+		.fetch_one(self.as_executor())
+		.await
+	}
+	/// Returns the presence of the users that share a chatroom with `user_id`
+	/// (or are `user_id`), all other users are left out
+	pub async fn visible_users_presence(
+		&mut self,
+		user_id: Uuid,
+		users: &[Uuid],
+	) -> sqlx::Result<Vec<(Uuid, Presence)>> {
+		sqlx::query!(
+			r#"SELECT u.id AS "user_id!", user_presence(u.id) AS "presence!: Presence"
+			FROM UNNEST($2::UUID[]) AS u(id)
+			WHERE
+				u.id = $1
+			OR
+				EXISTS(
+					SELECT 1
+					FROM chatroom_members mine
+					JOIN chatroom_members theirs ON mine.chatroom = theirs.chatroom
+					WHERE mine.user_id = $1 AND theirs.user_id = u.id
+				)"#,
+			user_id,
+			users
+		)
+		.fetch_all(self.as_executor())
+		.await
+		.map(|rows| {
+			rows.into_iter()
+				.map(|row| (row.user_id, row.presence))
+				.collect()
+		})
+	}
+}
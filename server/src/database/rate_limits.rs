@@ -0,0 +1,30 @@
+use super::{Database, ExecutorHack};
+use std::time::Duration;
+
+impl<D: ExecutorHack> Database<D> {
+	/// Takes a token from the bucket of `key`, which holds up to `capacity` tokens
+	/// and gets a new one every `refill_interval`.
+	///
+	/// Returns how long until a token can be taken if the bucket is empty
+	pub async fn take_rate_limit_token(
+		&mut self,
+		key: &str,
+		capacity: u32,
+		refill_interval: Duration,
+	) -> sqlx::Result<Option<Duration>> {
+		let wait_secs = sqlx::query_scalar!(
+			r#"SELECT take_rate_limit_token($1, $2, $3) AS "wait_secs!""#,
+			key,
+			capacity as f64,
+			refill_interval.as_secs_f64()
+		)
+		.fetch_one(self.as_executor())
+		.await?;
+
+		if wait_secs > 0.0 {
+			Ok(Some(Duration::from_secs_f64(wait_secs)))
+		} else {
+			Ok(None)
+		}
+	}
+}
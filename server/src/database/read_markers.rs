@@ -0,0 +1,54 @@
+use super::{Database, ExecutorHack};
+use uuid::Uuid;
+
+#[derive(Clone, Debug)]
+pub struct UnreadCount {
+	pub chatroom: Uuid,
+	pub last_read_seq_id: Option<i64>,
+	pub unread: i64,
+}
+
+impl<D: ExecutorHack> Database<D> {
+	/// Moves the read marker forward, never backwards.
+	///
+	/// Sequence ids past the last message in the chatroom are clamped to it.
+	pub async fn mark_read(
+		&mut self,
+		user_id: Uuid,
+		chatroom_id: Uuid,
+		seq_id: i64,
+	) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"INSERT INTO read_markers (user_id, chatroom, last_read_seq_id)
+			SELECT $1, $2, LEAST($3, next_sequence_id - 1)
+			FROM messages_sequential_ids
+			WHERE chatroom = $2 AND $3 >= 0
+			ON CONFLICT (user_id, chatroom) DO UPDATE
+			SET last_read_seq_id = EXCLUDED.last_read_seq_id
+			WHERE read_markers.last_read_seq_id < EXCLUDED.last_read_seq_id"#,
+			user_id,
+			chatroom_id,
+			seq_id
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+	/// Returns the unread counts of all chatrooms the user is a member of
+	pub async fn unread_counts(&mut self, user_id: Uuid) -> sqlx::Result<Vec<UnreadCount>> {
+		sqlx::query_as!(
+			UnreadCount,
+			r#"SELECT
+				m.chatroom,
+				r.last_read_seq_id AS "last_read_seq_id?",
+				COALESCE(s.next_sequence_id, 0) - COALESCE(r.last_read_seq_id + 1, 0) AS "unread!"
+			FROM chatroom_members m
+			LEFT JOIN messages_sequential_ids s ON s.chatroom = m.chatroom
+			LEFT JOIN read_markers r ON r.chatroom = m.chatroom AND r.user_id = m.user_id
+			WHERE m.user_id = $1"#,
+			user_id
+		)
+		.fetch_all(self.as_executor())
+		.await
+	}
+}
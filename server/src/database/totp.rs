@@ -0,0 +1,138 @@
+use super::{Database, ExecutorHack};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Clone, Debug)]
+pub struct UserTotp {
+	/// Encrypted with [`crate::totp::encrypt_secret`]
+	pub secret: Vec<u8>,
+	/// `false` while the enrolment wasnt confirmed yet
+	pub enabled: bool,
+}
+
+#[derive(Debug, Error)]
+#[error("TOTP is already enabled for the user")]
+pub struct TotpAlreadyEnabled;
+
+impl<D: ExecutorHack> Database<D> {
+	pub async fn user_totp(&mut self, user_id: Uuid) -> sqlx::Result<Option<UserTotp>> {
+		sqlx::query_as!(
+			UserTotp,
+			r#"SELECT secret, enabled FROM user_totp WHERE user_id = $1"#,
+			user_id
+		)
+		.fetch_optional(self.as_executor())
+		.await
+	}
+	pub async fn is_totp_enabled(&mut self, user_id: Uuid) -> sqlx::Result<bool> {
+		sqlx::query_scalar!(
+			r#"SELECT EXISTS(
+				SELECT 1 FROM user_totp WHERE user_id = $1 AND enabled
+			) AS "exists!""#,
+			user_id
+		)
+		.fetch_one(self.as_executor())
+		.await
+	}
+	/// Starts a new enrolment, replacing the previous one if it wasnt confirmed
+	pub async fn insert_pending_totp(
+		&mut self,
+		user_id: Uuid,
+		secret: &[u8],
+	) -> sqlx::Result<Result<(), TotpAlreadyEnabled>> {
+		sqlx::query!(
+			r#"
+			INSERT INTO user_totp (user_id, secret)
+			VALUES ($1, $2)
+			ON CONFLICT (user_id)
+			DO UPDATE SET
+			    secret = $2,
+			    last_used_step = NULL,
+			    created_at = NOW()
+			WHERE
+			    NOT user_totp.enabled"#,
+			user_id,
+			secret
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|r| {
+			if r.rows_affected() == 1 {
+				Ok(())
+			} else {
+				Err(TotpAlreadyEnabled)
+			}
+		})
+	}
+	pub async fn enable_totp(&mut self, user_id: Uuid) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"UPDATE user_totp SET enabled = TRUE WHERE user_id = $1"#,
+			user_id
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+	/// Also deletes the recovery codes
+	pub async fn delete_totp(&mut self, user_id: Uuid) -> sqlx::Result<()> {
+		sqlx::query!(r#"DELETE FROM user_totp WHERE user_id = $1"#, user_id)
+			.execute(self.as_executor())
+			.await
+			.map(|_| ())
+	}
+	/// Marks the time step as used.
+	///
+	/// Returns `false` if a code of this or a later step was already used
+	pub async fn use_totp_step(&mut self, user_id: Uuid, step: i64) -> sqlx::Result<bool> {
+		sqlx::query!(
+			r#"UPDATE user_totp
+			SET last_used_step = $2
+			WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)"#,
+			user_id,
+			step
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|r| r.rows_affected() == 1)
+	}
+	pub async fn replace_recovery_codes(
+		&mut self,
+		user_id: Uuid,
+		code_hashes: &[String],
+	) -> sqlx::Result<()> {
+		let mut transaction = self.transaction().await?;
+
+		sqlx::query!(
+			r#"DELETE FROM totp_recovery_codes WHERE user_id = $1"#,
+			user_id
+		)
+		.execute(transaction.as_executor())
+		.await?;
+
+		sqlx::query!(
+			r#"INSERT INTO totp_recovery_codes (user_id, code_hash)
+			SELECT $1, * FROM UNNEST($2::TEXT[])"#,
+			user_id,
+			code_hashes
+		)
+		.execute(transaction.as_executor())
+		.await?;
+
+		transaction.commit().await
+	}
+	/// Returns `false` if the user has no such recovery code
+	pub async fn use_recovery_code(
+		&mut self,
+		user_id: Uuid,
+		code_hash: &str,
+	) -> sqlx::Result<bool> {
+		sqlx::query!(
+			r#"DELETE FROM totp_recovery_codes WHERE user_id = $1 AND code_hash = $2"#,
+			user_id,
+			code_hash
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|r| r.rows_affected() == 1)
+	}
+}
@@ -0,0 +1,26 @@
+use super::{Database, ExecutorHack};
+use uuid::Uuid;
+
+impl<D: ExecutorHack> Database<D> {
+	/// Notifies all server instances listening on the chatroom, nothing is stored
+	pub async fn notify_typing(
+		&mut self,
+		chatroom_id: Uuid,
+		user_id: Uuid,
+		typing: bool,
+	) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"SELECT pg_notify(
+				'typing-' || $1::UUID,
+				jsonb_build_object('user_id', $2::UUID, 'typing', $3::BOOLEAN)::text
+			)"#,
+			chatroom_id,
+			user_id,
+			typing
+		)
+		.execute(self.as_executor())
+		.await?;
+
+		Ok(())
+	}
+}
@@ -13,18 +13,9 @@ pub struct User {
 
 pub struct UsernameConflict;
 
+pub struct EmailConflict;
+
 impl<D: ExecutorHack> Database<D> {
-	pub async fn user_by_auth_token(&mut self, token: Uuid) -> sqlx::Result<Option<User>> {
-		sqlx::query_as!(
-			User,
-			r#"SELECT u.id, u.username, u.email, u.password, u.last_account_reminder_sent
-			FROM active_sessions JOIN users AS u ON active_sessions.user_id = u.id
-			WHERE active_sessions.token = $1 AND active_sessions.expires_at > NOW()"#,
-			token,
-		)
-		.fetch_optional(self.as_executor())
-		.await
-	}
 	pub async fn user_by_id(&mut self, id: Uuid) -> sqlx::Result<Option<User>> {
 		sqlx::query_as!(
 			User,
@@ -88,4 +79,66 @@ impl<D: ExecutorHack> Database<D> {
 			Err(e) => Err(e),
 		}
 	}
+	pub async fn set_user_password(&mut self, user_id: Uuid, password: &str) -> sqlx::Result<()> {
+		sqlx::query!(
+			r#"UPDATE users SET password = $2 WHERE id = $1"#,
+			user_id,
+			password
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|_| ())
+	}
+	pub async fn set_user_email(
+		&mut self,
+		user_id: Uuid,
+		email: &str,
+	) -> sqlx::Result<Result<(), EmailConflict>> {
+		match sqlx::query!(
+			r#"UPDATE users SET email = $2 WHERE id = $1"#,
+			user_id,
+			email
+		)
+		.execute(self.as_executor())
+		.await
+		{
+			Ok(_) => Ok(Ok(())),
+			Err(sqlx::Error::Database(db_err)) => {
+				if db_err.is_unique_violation() && db_err.constraint() == Some("users_email_key") {
+					Ok(Err(EmailConflict))
+				} else {
+					Err(sqlx::Error::Database(db_err))
+				}
+			}
+			Err(e) => Err(e),
+		}
+	}
+	/// Schedules the account to be purged after `grace_period_days`, returns when that will happen
+	pub async fn schedule_user_deletion(
+		&mut self,
+		user_id: Uuid,
+		grace_period_days: u32,
+	) -> sqlx::Result<DateTime<Utc>> {
+		sqlx::query_scalar!(
+			r#"UPDATE users
+			SET deletion_scheduled_at = NOW() + ('1 day'::interval * $2)
+			WHERE id = $1
+			RETURNING deletion_scheduled_at AS "deletion_scheduled_at!""#,
+			user_id,
+			grace_period_days as i64
+		)
+		.fetch_one(self.as_executor())
+		.await
+	}
+	/// Returns `false` if no deletion was scheduled
+	pub async fn cancel_user_deletion(&mut self, user_id: Uuid) -> sqlx::Result<bool> {
+		sqlx::query!(
+			r#"UPDATE users SET deletion_scheduled_at = NULL
+			WHERE id = $1 AND deletion_scheduled_at IS NOT NULL"#,
+			user_id
+		)
+		.execute(self.as_executor())
+		.await
+		.map(|r| r.rows_affected() == 1)
+	}
 }
@@ -60,7 +60,77 @@ async fn clean(state: &mut ServerState) -> Result<()> {
 					.await?;
 			}
 			"active_sessions" => {
-				sqlx::query!("DELETE FROM active_sessions WHERE expires_at < NOW()")
+				sqlx::query!(
+					"DELETE FROM active_sessions WHERE COALESCE(refresh_expires_at, expires_at) < NOW()"
+				)
+				.execute(transaction.as_mut())
+				.await?;
+			}
+			"password_resets" => {
+				sqlx::query!("DELETE FROM password_resets WHERE expires_at < NOW()")
+					.execute(transaction.as_mut())
+					.await?;
+			}
+			"chatroom_invites" => {
+				sqlx::query!(
+					"DELETE FROM chatroom_invites WHERE expires_at < NOW() OR uses >= max_uses"
+				)
+				.execute(transaction.as_mut())
+				.await?;
+			}
+			"login_challenges" => {
+				sqlx::query!("DELETE FROM login_challenges WHERE expires_at < NOW()")
+					.execute(transaction.as_mut())
+					.await?;
+			}
+			"rate_limits" => {
+				sqlx::query!("DELETE FROM rate_limits WHERE full_at < NOW()")
+					.execute(transaction.as_mut())
+					.await?;
+			}
+			"users" => {
+				// chatrooms owned by purged users are handed to the highest ranking member,
+				// the one that joined first if there are several
+				sqlx::query!(
+					r#"UPDATE chatroom_members cm
+					SET role = 'owner'
+					FROM (
+						SELECT DISTINCT ON (heir.chatroom) heir.chatroom, heir.user_id
+						FROM chatroom_members heir
+						JOIN chatroom_members owner
+							ON owner.chatroom = heir.chatroom AND owner.role = 'owner'
+						JOIN users owner_user
+							ON owner_user.id = owner.user_id AND owner_user.deletion_scheduled_at < NOW()
+						JOIN users heir_user
+							ON heir_user.id = heir.user_id
+						WHERE heir.user_id != owner.user_id
+						AND (heir_user.deletion_scheduled_at IS NULL OR heir_user.deletion_scheduled_at >= NOW())
+						ORDER BY heir.chatroom, heir.role DESC, heir.joined_at ASC
+					) new_owner
+					WHERE cm.chatroom = new_owner.chatroom AND cm.user_id = new_owner.user_id"#
+				)
+				.execute(transaction.as_mut())
+				.await?;
+
+				// chatrooms where all members are purged, including direct chats between two purged users
+				sqlx::query!(
+					r#"DELETE FROM chatrooms c
+					WHERE
+						EXISTS(SELECT 1 FROM chatroom_members m WHERE m.chatroom = c.id)
+					AND
+						NOT EXISTS(
+							SELECT 1
+							FROM chatroom_members m
+							JOIN users u ON u.id = m.user_id
+							WHERE m.chatroom = c.id
+							AND (u.deletion_scheduled_at IS NULL OR u.deletion_scheduled_at >= NOW())
+						)"#
+				)
+				.execute(transaction.as_mut())
+				.await?;
+
+				// messages and direct chats of the users are kept without them
+				sqlx::query!("DELETE FROM users WHERE deletion_scheduled_at < NOW()")
 					.execute(transaction.as_mut())
 					.await?;
 			}
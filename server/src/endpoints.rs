@@ -1,2 +1,3 @@
 pub mod auth;
+pub mod invite;
 pub mod main;
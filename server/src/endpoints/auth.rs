@@ -1,8 +1,22 @@
 use crate::ServerState;
 use axum::Router;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use sha2::{Digest, Sha256};
 
 mod v1;
+mod v2;
+
+/// How long a session lasts without being used
+pub const SESSION_LIFETIME: u32 = 15 * 24; // in hours
 
 pub fn auth_routes() -> Router<ServerState> {
-	Router::new().nest("/v1", v1::routes())
+	Router::new()
+		.nest("/v1", v1::routes())
+		.nest("/v2", v2::routes())
+}
+
+/// Only the hashes of tokens are stored in the database,
+/// such as auth tokens, email links and password resets
+pub fn hash_token(token: impl AsRef<[u8]>) -> String {
+	BASE64_STANDARD.encode(Sha256::digest(token))
 }
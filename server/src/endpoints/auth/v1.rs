@@ -1,14 +1,20 @@
+use super::{SESSION_LIFETIME, hash_token};
 use crate::{
 	ServerState,
 	database::{
+		active_sessions::{ActiveSession, SessionMetadata},
 		email_verifications::{EmailAlreadyAdded, VerifyEmailError},
-		user::UsernameConflict,
+		password_resets::PasswordResetPending,
+		totp::TotpAlreadyEnabled,
+		user::{EmailConflict, User, UsernameConflict},
 	},
 	pages::{
 		self,
 		email_verification::{code_not_found_page, code_page},
 		internal_error_page,
 	},
+	rate_limit::{Action, Key, rate_limit},
+	totp,
 };
 use anyhow::Context;
 use argon2::{
@@ -22,25 +28,32 @@ use axum::{
 	response::{Html, IntoResponse},
 	routing::{get, post},
 };
-use axum::{extract::Path, http::StatusCode};
-use base64::{Engine, prelude::BASE64_STANDARD};
+use axum::{
+	extract::{ConnectInfo, Path},
+	http::{StatusCode, header::RETRY_AFTER},
+};
 use chrono::Utc;
 use email_address::EmailAddress;
 use protocol::auth::{
 	Request,
 	v1::{self, *},
 };
-use rand::Rng;
-use sha2::{Digest, Sha256};
-use std::borrow::Cow;
+use rand::{Rng, distr::Alphanumeric};
+use std::{
+	borrow::Cow,
+	net::{IpAddr, SocketAddr},
+};
 use thiserror::Error;
-use tracing::error;
+use tokio::spawn;
+use tracing::{error, info};
 use uuid::Uuid;
 
-const AUTH_TOKEN_LIFETIME: u32 = 15 * 24; // in hours
 const EMAIL_VERIFICATION_LIFETIME: u32 = 60; // in minutes
 const REGISTRATION_LIFETIME: u32 = 60; // in minutes
 const EMAIL_ACCOUNT_REMIND_TIMEOUT: i64 = 7 * 24; // in hours
+const PASSWORD_RESET_LIFETIME: u32 = 30; // in minutes
+const PASSWORD_RESET_TOKEN_LEN: usize = 32;
+const LOGIN_CHALLENGE_LIFETIME: u32 = 5; // in minutes
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -57,31 +70,74 @@ impl IntoResponse for Error {
 		error!("{self:?}");
 		match self {
 			Error::Database(_) | Error::Internal(_) | Error::Api(v1::Error::Internal) => {
-				(StatusCode::INTERNAL_SERVER_ERROR, Json(v1::Error::Internal))
+				(StatusCode::INTERNAL_SERVER_ERROR, Json(v1::Error::Internal)).into_response()
 			}
-			Error::Api(e) => (StatusCode::BAD_REQUEST, Json(e)),
+			Error::Api(e @ v1::Error::RateLimited { retry_after }) => (
+				StatusCode::TOO_MANY_REQUESTS,
+				[(RETRY_AFTER, retry_after.to_string())],
+				Json(e),
+			)
+				.into_response(),
+			Error::Api(e) => (StatusCode::BAD_REQUEST, Json(e)).into_response(),
 		}
-		.into_response()
 	}
 }
 
 pub fn routes() -> Router<ServerState> {
 	Router::new()
 		.route(LoginRequest::PATH, post(login))
+		.route(TwoFactorLoginRequest::PATH, post(login_two_factor))
 		.route(StartEmailVerifyRequest::PATH, post(start_email_verify))
 		.route(VerifyEmailRequest::PATH, post(verify_email))
 		.route(FinalizeNewAccountRequest::PATH, post(finalize_new_account))
+		.route(RequestPasswordReset::PATH, post(request_password_reset))
+		.route(CompletePasswordReset::PATH, post(complete_password_reset))
+		.route(LogoutRequest::PATH, post(logout))
+		.route(ListSessionsRequest::PATH, post(list_sessions))
+		.route(RevokeSessionRequest::PATH, post(revoke_session))
+		.route(
+			RevokeOtherSessionsRequest::PATH,
+			post(revoke_other_sessions),
+		)
+		.route(ChangePasswordRequest::PATH, post(change_password))
+		.route(StartEmailChangeRequest::PATH, post(start_email_change))
+		.route(
+			CompleteEmailChangeRequest::PATH,
+			post(complete_email_change),
+		)
+		.route(DeleteAccountRequest::PATH, post(delete_account))
+		.route(
+			StartTotpEnrollmentRequest::PATH,
+			post(start_totp_enrollment),
+		)
+		.route(
+			ConfirmTotpEnrollmentRequest::PATH,
+			post(confirm_totp_enrollment),
+		)
+		.route(DisableTotpRequest::PATH, post(disable_totp))
+		.route(
+			RegenerateRecoveryCodesRequest::PATH,
+			post(regenerate_recovery_codes),
+		)
 		.route("/verify/{token}", get(display_verification_code))
 }
 
 async fn start_email_verify(
 	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
 	Json(request): Json<StartEmailVerifyRequest>,
 ) -> Result<Json<()>, Error> {
 	if !EmailAddress::is_valid(&request.email) {
 		return Err(v1::Error::InvalidRequest.into());
 	}
 
+	check_rate_limit(
+		&mut state,
+		Action::Email,
+		&[Key::Ip(addr.ip()), Key::Email(&request.email)],
+	)
+	.await?;
+
 	match state.db.user_by_email(&request.email).await? {
 		Some(user) => {
 			// some user is already registered with this email.
@@ -102,7 +158,7 @@ async fn start_email_verify(
 		}
 		None => {
 			let link_token = Uuid::now_v7();
-			let link_token_hash = hash_link_token(link_token);
+			let link_token_hash = hash_token(link_token);
 
 			let code = rand::rng().random_range(0..10000); // 4 digits
 
@@ -171,67 +227,557 @@ async fn verify_email(
 
 async fn finalize_new_account(
 	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
 	Json(request): Json<FinalizeNewAccountRequest>,
 ) -> Result<Json<LoginSuccess>, Error> {
+	let user_id = create_account(
+		&mut state,
+		request.registration_id,
+		&request.username,
+		&request.password,
+	)
+	.await?;
+
+	let auth_token = new_auth_session(&mut state, user_id, request.client.as_ref(), addr).await?;
+
+	Ok(Json(LoginSuccess { auth_token }))
+}
+
+async fn login(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<LoginRequest>,
+) -> Result<Json<LoginResponse>, Error> {
+	let user_id =
+		check_credentials(&mut state, &request.email, &request.password, addr.ip()).await?;
+
+	if let Some(challenge) =
+		start_two_factor(&mut state, user_id, request.client.as_ref(), None).await?
+	{
+		return Ok(Json(LoginResponse::TwoFactorRequired(challenge)));
+	}
+
+	let token = new_auth_session(&mut state, user_id, request.client.as_ref(), addr).await?;
+
+	Ok(Json(LoginResponse::Success(LoginSuccess {
+		auth_token: token,
+	})))
+}
+
+async fn login_two_factor(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<TwoFactorLoginRequest>,
+) -> Result<Json<LoginSuccess>, Error> {
+	let (user_id, client) =
+		complete_two_factor(&mut state, request.challenge, &request.code, addr.ip()).await?;
+
+	let auth_token = new_auth_session(&mut state, user_id, client.as_ref(), addr).await?;
+
+	Ok(Json(LoginSuccess { auth_token }))
+}
+
+async fn request_password_reset(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<RequestPasswordReset>,
+) -> Result<Json<()>, Error> {
+	if !EmailAddress::is_valid(&request.email) {
+		return Err(v1::Error::InvalidRequest.into());
+	}
+
+	// limited whether the email is registered or not, so that it doesnt reveal it either
+	check_rate_limit(
+		&mut state,
+		Action::Email,
+		&[Key::Ip(addr.ip()), Key::Email(&request.email)],
+	)
+	.await?;
+
+	// the response is the same whether an account exists or not,
+	// so that it doesnt reveal which emails are registered
+	let user = match state.db.user_by_email(&request.email).await? {
+		Some(x) => x,
+		None => return Ok(Json(())),
+	};
+
+	let token: String = rand::rng()
+		.sample_iter(Alphanumeric)
+		.take(PASSWORD_RESET_TOKEN_LEN)
+		.map(char::from)
+		.collect();
+
+	if let Err(PasswordResetPending) = state
+		.db
+		.insert_password_reset(user.id, &hash_token(&token), PASSWORD_RESET_LIFETIME)
+		.await?
+	{
+		// email must be already sent. no need to send it again
+		return Ok(Json(()));
+	}
+
+	// waiting for the email to be sent would make the response noticeably slower for registered emails
+	spawn(async move {
+		if let Err(e) = state
+			.email
+			.send_noreply_email(
+				&request.email,
+				"Salix Password Reset",
+				&pages::email::password_reset(&token),
+			)
+			.await
+		{
+			error!("error sending password reset email: {e:?}");
+		}
+	});
+
+	Ok(Json(()))
+}
+
+async fn complete_password_reset(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<CompletePasswordReset>,
+) -> Result<Json<LoginResponse>, Error> {
+	let password_hash = hash_password(&request.new_password).context("hash password for reset")?;
+
+	let user_id = state
+		.db
+		.take_password_reset(&hash_token(&request.token))
+		.await?
+		.ok_or(v1::Error::IncorrectCode)?;
+
+	// the reset email alone must not be enough to get past the second factor,
+	// so the password is only changed once the login is completed
+	if let Some(challenge) = start_two_factor(
+		&mut state,
+		user_id,
+		request.client.as_ref(),
+		Some(&password_hash),
+	)
+	.await?
+	{
+		return Ok(Json(LoginResponse::TwoFactorRequired(challenge)));
+	}
+
+	state
+		.db
+		.apply_password_reset(user_id, &password_hash)
+		.await?;
+
+	let auth_token = new_auth_session(&mut state, user_id, request.client.as_ref(), addr).await?;
+
+	Ok(Json(LoginResponse::Success(LoginSuccess { auth_token })))
+}
+
+async fn logout(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<LogoutRequest>,
+) -> Result<Json<()>, Error> {
+	let session = use_session(&mut state, request.auth_token, addr).await?;
+
+	state
+		.db
+		.delete_user_session(session.user_id, session.id)
+		.await?;
+
+	Ok(Json(()))
+}
+
+async fn list_sessions(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<ListSessionsRequest>,
+) -> Result<Json<SessionList>, Error> {
+	let current = use_session(&mut state, request.auth_token, addr).await?;
+
+	let sessions = state
+		.db
+		.user_active_sessions(current.user_id)
+		.await?
+		.into_iter()
+		.map(|session| SessionInfo {
+			id: session.id,
+			created_at: session.created_at.timestamp_millis(),
+			last_used_at: session.last_used_at.timestamp_millis(),
+			client: session
+				.client_name
+				.zip(session.client_version)
+				.map(|(name, version)| ClientInfo { name, version }),
+			ip: session.ip,
+			current: session.id == current.id,
+		})
+		.collect();
+
+	Ok(Json(SessionList { sessions }))
+}
+
+async fn revoke_session(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<RevokeSessionRequest>,
+) -> Result<Json<()>, Error> {
+	let current = use_session(&mut state, request.auth_token, addr).await?;
+
+	// connections of the session are closed through the user events
+	if !state
+		.db
+		.delete_user_session(current.user_id, request.session_id)
+		.await?
+	{
+		return Err(v1::Error::InvalidRequest.into());
+	}
+
+	Ok(Json(()))
+}
+
+async fn revoke_other_sessions(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<RevokeOtherSessionsRequest>,
+) -> Result<Json<()>, Error> {
+	let current = use_session(&mut state, request.auth_token, addr).await?;
+
+	state
+		.db
+		.delete_other_user_sessions(current.user_id, current.id)
+		.await?;
+
+	Ok(Json(()))
+}
+
+async fn change_password(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<ChangePasswordRequest>,
+) -> Result<Json<()>, Error> {
+	let current = use_session(&mut state, request.auth_token, addr).await?;
+
+	let user = state
+		.db
+		.user_by_id(current.user_id)
+		.await?
+		.context("user of session doesnt exist")?;
+
+	check_password(&mut state, &user, &request.current_password, addr.ip()).await?;
+
+	let password_hash = hash_password(&request.new_password).context("hash password for change")?;
+
 	let mut transaction = state.db.transaction().await?;
 
-	let registration = match transaction
-		.get_registration(request.registration_id)
+	transaction
+		.set_user_password(user.id, &password_hash)
+		.await?;
+	if request.revoke_other_sessions {
+		transaction
+			.delete_other_user_sessions(user.id, current.id)
+			.await?;
+	}
+
+	transaction.commit().await?;
+
+	Ok(Json(()))
+}
+
+async fn start_email_change(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<StartEmailChangeRequest>,
+) -> Result<Json<()>, Error> {
+	let current = use_session(&mut state, request.auth_token, addr).await?;
+
+	if !EmailAddress::is_valid(&request.new_email) {
+		return Err(v1::Error::InvalidRequest.into());
+	}
+
+	let user = state
+		.db
+		.user_by_id(current.user_id)
+		.await?
+		.context("user of session doesnt exist")?;
+
+	check_password(&mut state, &user, &request.current_password, addr.ip()).await?;
+
+	check_rate_limit(
+		&mut state,
+		Action::Email,
+		&[Key::Ip(addr.ip()), Key::User(user.id)],
+	)
+	.await?;
+
+	// dont reveal whether the email is registered, the code just never arrives
+	if state.db.user_by_email(&request.new_email).await?.is_some() {
+		return Ok(Json(()));
+	}
+
+	let link_token = Uuid::now_v7();
+	let code = rand::rng().random_range(0..10000); // 4 digits
+
+	if let Err(EmailAlreadyAdded) = state
+		.db
+		.insert_email_verification(
+			&request.new_email,
+			&hash_token(link_token),
+			code,
+			EMAIL_VERIFICATION_LIFETIME,
+		)
 		.await?
 	{
+		// email must be already sent. no need to send it again
+		return Ok(Json(()));
+	}
+
+	let link = state
+		.config
+		.public_base_url
+		.join("auth/v1/verify/")
+		.unwrap()
+		.join(&link_token.to_string())
+		.unwrap();
+
+	state
+		.email
+		.send_noreply_email(
+			&request.new_email,
+			"Salix Email Change",
+			&pages::email::email_change_code(link.as_str()),
+		)
+		.await?;
+
+	Ok(Json(()))
+}
+
+async fn complete_email_change(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<CompleteEmailChangeRequest>,
+) -> Result<Json<()>, Error> {
+	let current = use_session(&mut state, request.auth_token, addr).await?;
+
+	if let Err(e) = state
+		.db
+		.verify_email(&request.new_email, request.code)
+		.await?
+	{
+		// same as when creating an account, dont reveal whether a code was generated
+		match e {
+			VerifyEmailError::IncorrectCode => return Err(v1::Error::IncorrectCode.into()),
+			VerifyEmailError::TooManyAttempts => return Err(v1::Error::IncorrectCode.into()),
+			VerifyEmailError::Invalid => return Err(v1::Error::IncorrectCode.into()),
+		}
+	}
+
+	let user = state
+		.db
+		.user_by_id(current.user_id)
+		.await?
+		.context("user of session doesnt exist")?;
+
+	// someone registered with the email after the code was sent
+	state
+		.db
+		.set_user_email(user.id, &request.new_email)
+		.await?
+		.map_err(|EmailConflict| v1::Error::InvalidRequest)?;
+
+	// the email is already changed, failing to send the notice shouldnt fail the request
+	spawn(async move {
+		if let Err(e) = state
+			.email
+			.send_noreply_email(
+				&user.email,
+				"Salix Email Changed",
+				&pages::email::email_changed(&request.new_email),
+			)
+			.await
+		{
+			error!("error sending email change notice: {e:?}");
+		}
+	});
+
+	Ok(Json(()))
+}
+
+async fn delete_account(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<DeleteAccountRequest>,
+) -> Result<Json<AccountDeletionScheduled>, Error> {
+	let current = use_session(&mut state, request.auth_token, addr).await?;
+
+	let user = state
+		.db
+		.user_by_id(current.user_id)
+		.await?
+		.context("user of session doesnt exist")?;
+
+	check_password(&mut state, &user, &request.password, addr.ip()).await?;
+
+	let mut transaction = state.db.transaction().await?;
+
+	let deletion_at = transaction
+		.schedule_user_deletion(user.id, state.config.account_deletion_grace_period)
+		.await?;
+	// logging in again is what cancels the deletion
+	transaction.delete_user_sessions(user.id).await?;
+
+	transaction.commit().await?;
+
+	spawn(async move {
+		let deletion_date = deletion_at.format("%B %-d, %Y").to_string();
+
+		if let Err(e) = state
+			.email
+			.send_noreply_email(
+				&user.email,
+				"Salix Account Deletion",
+				&pages::email::account_deletion(&deletion_date),
+			)
+			.await
+		{
+			error!("error sending account deletion email: {e:?}");
+		}
+	});
+
+	Ok(Json(AccountDeletionScheduled {
+		deletion_at: deletion_at.timestamp_millis(),
+	}))
+}
+
+async fn start_totp_enrollment(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<StartTotpEnrollmentRequest>,
+) -> Result<Json<TotpEnrollment>, Error> {
+	let current = use_session(&mut state, request.auth_token, addr).await?;
+
+	let key = match state.config.totp_secret_key {
 		Some(x) => x,
+		// two-factor authentication isnt set up on this server
 		None => return Err(v1::Error::InvalidRequest.into()),
 	};
 
-	// hash the password
-	let salt = SaltString::generate(&mut OsRng);
-	let password_hash = argon2()
-		.hash_password(request.password.as_bytes(), &salt)
-		.with_context(|| format!("{request:?}"))?
-		.to_string();
+	let user = state
+		.db
+		.user_by_id(current.user_id)
+		.await?
+		.context("user of session doesnt exist")?;
 
-	let user_id = transaction
-		.insert_user(&request.username, &registration.email, &password_hash)
+	check_password(&mut state, &user, &request.password, addr.ip()).await?;
+
+	let secret = totp::generate_secret();
+	let encrypted = totp::encrypt_secret(&key, user.id, &secret);
+
+	state
+		.db
+		.insert_pending_totp(user.id, &encrypted)
 		.await?
-		.map_err(|UsernameConflict| v1::Error::UsernameConflict)?;
+		.map_err(|TotpAlreadyEnabled| v1::Error::InvalidRequest)?;
+
+	Ok(Json(TotpEnrollment {
+		secret: totp::encode_secret(&secret),
+		provisioning_uri: totp::provisioning_uri(&secret, &user.email),
+	}))
+}
+
+async fn confirm_totp_enrollment(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<ConfirmTotpEnrollmentRequest>,
+) -> Result<Json<RecoveryCodes>, Error> {
+	let current = use_session(&mut state, request.auth_token, addr).await?;
+
+	check_rate_limit(
+		&mut state,
+		Action::Login,
+		&[Key::Ip(addr.ip()), Key::User(current.user_id)],
+	)
+	.await?;
 
+	let pending = match state.db.user_totp(current.user_id).await? {
+		Some(x) if !x.enabled => x,
+		_ => return Err(v1::Error::InvalidRequest.into()),
+	};
+
+	let key = state
+		.config
+		.totp_secret_key
+		.context("totp_secret_key isnt configured")?;
+	let secret = totp::decrypt_secret(&key, current.user_id, &pending.secret)
+		.context("decrypt TOTP secret")?;
+
+	let step = totp::verify(&secret, &request.code).ok_or(v1::Error::IncorrectCode)?;
+
+	let codes = totp::generate_recovery_codes();
+	let code_hashes: Vec<String> = codes.iter().map(|c| hash_recovery_code(c)).collect();
+
+	let mut transaction = state.db.transaction().await?;
+
+	transaction.use_totp_step(current.user_id, step).await?;
+	transaction.enable_totp(current.user_id).await?;
 	transaction
-		.remove_registration(request.registration_id)
+		.replace_recovery_codes(current.user_id, &code_hashes)
 		.await?;
 
 	transaction.commit().await?;
 
-	let auth_token = new_auth_session(&mut state, user_id).await?;
+	Ok(Json(RecoveryCodes { codes }))
+}
 
-	Ok(Json(LoginSuccess { auth_token }))
+async fn disable_totp(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<DisableTotpRequest>,
+) -> Result<Json<()>, Error> {
+	let current = use_session(&mut state, request.auth_token, addr).await?;
+
+	let user = state
+		.db
+		.user_by_id(current.user_id)
+		.await?
+		.context("user of session doesnt exist")?;
+
+	check_password(&mut state, &user, &request.password, addr.ip()).await?;
+	check_second_factor(&mut state, user.id, &request.code).await?;
+
+	state.db.delete_totp(user.id).await?;
+
+	Ok(Json(()))
 }
 
-async fn login(
+async fn regenerate_recovery_codes(
 	State(mut state): State<ServerState>,
-	Json(request): Json<LoginRequest>,
-) -> Result<Json<LoginSuccess>, Error> {
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<RegenerateRecoveryCodesRequest>,
+) -> Result<Json<RecoveryCodes>, Error> {
+	let current = use_session(&mut state, request.auth_token, addr).await?;
+
 	let user = state
 		.db
-		.user_by_email(&request.email)
+		.user_by_id(current.user_id)
 		.await?
-		.ok_or(v1::Error::Unauthorized)?;
+		.context("user of session doesnt exist")?;
 
-	let hash = PasswordHash::new(&user.password).with_context(|| format!("{user:?}"))?;
+	check_password(&mut state, &user, &request.password, addr.ip()).await?;
+	check_second_factor(&mut state, user.id, &request.code).await?;
 
-	argon2()
-		.verify_password(request.password.as_bytes(), &hash)
-		.map_err(|_| v1::Error::Unauthorized)?;
+	let codes = totp::generate_recovery_codes();
+	let code_hashes: Vec<String> = codes.iter().map(|c| hash_recovery_code(c)).collect();
 
-	let token = new_auth_session(&mut state, user.id).await?;
+	state
+		.db
+		.replace_recovery_codes(user.id, &code_hashes)
+		.await?;
 
-	Ok(Json(LoginSuccess { auth_token: token }))
+	Ok(Json(RecoveryCodes { codes }))
 }
 
 async fn display_verification_code(
 	State(mut state): State<ServerState>,
 	Path(link_token): Path<Uuid>,
 ) -> Html<Cow<'static, str>> {
-	let hash = hash_link_token(link_token);
+	let hash = hash_token(link_token);
 
 	let code = match state.db.get_email_verification_code(&hash).await {
 		Ok(Some(x)) => x,
@@ -247,15 +793,269 @@ async fn display_verification_code(
 	Html(code_page(code).into())
 }
 
-fn hash_link_token(token: Uuid) -> String {
-	BASE64_STANDARD.encode(Sha256::digest(token.as_bytes()))
+/// Creates the account of a finished registration
+pub(super) async fn create_account(
+	state: &mut ServerState,
+	registration_id: Uuid,
+	username: &str,
+	password: &str,
+) -> Result<Uuid, Error> {
+	let mut transaction = state.db.transaction().await?;
+
+	let registration = match transaction.get_registration(registration_id).await? {
+		Some(x) => x,
+		None => return Err(v1::Error::InvalidRequest.into()),
+	};
+
+	let password_hash = hash_password(password).context("hash password for new account")?;
+
+	let user_id = transaction
+		.insert_user(username, &registration.email, &password_hash)
+		.await?
+		.map_err(|UsernameConflict| v1::Error::UsernameConflict)?;
+
+	transaction.remove_registration(registration_id).await?;
+
+	transaction.commit().await?;
+
+	Ok(user_id)
 }
 
-async fn new_auth_session(state: &mut ServerState, user_id: Uuid) -> sqlx::Result<Uuid> {
+/// Returns the id of the user, or [`v1::Error::Unauthorized`] if the email or password is wrong
+pub(super) async fn check_credentials(
+	state: &mut ServerState,
+	email: &str,
+	password: &str,
+	ip: IpAddr,
+) -> Result<Uuid, Error> {
+	// before looking up the user, so that guessing emails is limited too
+	check_rate_limit(state, Action::Login, &[Key::Ip(ip), Key::Email(email)]).await?;
+
+	let user = state
+		.db
+		.user_by_email(email)
+		.await?
+		.ok_or(v1::Error::Unauthorized)?;
+
+	verify_password(&user, password)?;
+
+	Ok(user.id)
+}
+
+/// Returns a challenge for the second step of logging in if the user has two-factor authentication enabled,
+/// otherwise the login is completed. `new_password` is applied once the challenge is completed
+pub(super) async fn start_two_factor(
+	state: &mut ServerState,
+	user_id: Uuid,
+	client: Option<&ClientInfo>,
+	new_password: Option<&str>,
+) -> Result<Option<TwoFactorChallenge>, Error> {
+	if !state.db.is_totp_enabled(user_id).await? {
+		finish_login(state, user_id).await?;
+		return Ok(None);
+	}
+
+	let challenge = Uuid::now_v7();
+
 	state
 		.db
-		.insert_active_session(user_id, AUTH_TOKEN_LIFETIME)
-		.await
+		.insert_login_challenge(
+			&hash_token(challenge),
+			user_id,
+			LOGIN_CHALLENGE_LIFETIME,
+			client.map(|c| c.name.as_str()),
+			client.map(|c| c.version.as_str()),
+			new_password,
+		)
+		.await?;
+
+	Ok(Some(TwoFactorChallenge { challenge }))
+}
+
+/// Returns the user and the client that started the login
+pub(super) async fn complete_two_factor(
+	state: &mut ServerState,
+	challenge: Uuid,
+	code: &str,
+	ip: IpAddr,
+) -> Result<(Uuid, Option<ClientInfo>), Error> {
+	let challenge_hash = hash_token(challenge);
+
+	let login = state
+		.db
+		.attempt_login_challenge(&challenge_hash)
+		.await?
+		.ok_or(v1::Error::Unauthorized)?;
+
+	check_rate_limit(
+		state,
+		Action::Login,
+		&[Key::Ip(ip), Key::User(login.user_id)],
+	)
+	.await?;
+	check_second_factor(state, login.user_id, code).await?;
+
+	let mut transaction = state.db.transaction().await?;
+
+	transaction.delete_login_challenge(&challenge_hash).await?;
+	if let Some(password_hash) = &login.new_password {
+		transaction
+			.apply_password_reset(login.user_id, password_hash)
+			.await?;
+	}
+
+	transaction.commit().await?;
+
+	finish_login(state, login.user_id).await?;
+
+	let client = login
+		.client_name
+		.zip(login.client_version)
+		.map(|(name, version)| ClientInfo { name, version });
+
+	Ok((login.user_id, client))
+}
+
+/// Called once all factors of a login were checked
+async fn finish_login(state: &mut ServerState, user_id: Uuid) -> Result<(), Error> {
+	if state.db.cancel_user_deletion(user_id).await? {
+		info!("deletion of user {user_id} cancelled by logging in");
+	}
+
+	Ok(())
+}
+
+/// Checks a TOTP or recovery code of the user, each can only be used once.
+/// Fails with [`v1::Error::IncorrectCode`]
+async fn check_second_factor(
+	state: &mut ServerState,
+	user_id: Uuid,
+	code: &str,
+) -> Result<(), Error> {
+	let secret = match state.db.user_totp(user_id).await? {
+		Some(x) if x.enabled => x.secret,
+		_ => return Err(v1::Error::InvalidRequest.into()),
+	};
+	let key = state
+		.config
+		.totp_secret_key
+		.context("totp_secret_key isnt configured")?;
+	let secret = totp::decrypt_secret(&key, user_id, &secret).context("decrypt TOTP secret")?;
+
+	let valid = if totp::is_totp_code(code) {
+		match totp::verify(&secret, code) {
+			// a code that was already used could have been seen by someone else
+			Some(step) => state.db.use_totp_step(user_id, step).await?,
+			None => false,
+		}
+	} else {
+		state
+			.db
+			.use_recovery_code(user_id, &hash_recovery_code(code))
+			.await?
+	};
+
+	if !valid {
+		return Err(v1::Error::IncorrectCode.into());
+	}
+
+	Ok(())
+}
+
+/// Same as [`verify_password`], but limits how many passwords can be tried for the user
+async fn check_password(
+	state: &mut ServerState,
+	user: &User,
+	password: &str,
+	ip: IpAddr,
+) -> Result<(), Error> {
+	check_rate_limit(state, Action::Login, &[Key::Ip(ip), Key::User(user.id)]).await?;
+
+	verify_password(user, password)
+}
+
+/// Fails with [`v1::Error::RateLimited`] if any of the buckets of `keys` is empty
+async fn check_rate_limit(
+	state: &mut ServerState,
+	action: Action,
+	keys: &[Key<'_>],
+) -> Result<(), Error> {
+	rate_limit(state, action, keys).await?.map_err(|limited| {
+		v1::Error::RateLimited {
+			retry_after: limited.retry_after_secs(),
+		}
+		.into()
+	})
+}
+
+/// Fails with [`v1::Error::Unauthorized`] if the password of the user isnt `password`
+fn verify_password(user: &User, password: &str) -> Result<(), Error> {
+	let hash = PasswordHash::new(&user.password).with_context(|| format!("{user:?}"))?;
+
+	argon2()
+		.verify_password(password.as_bytes(), &hash)
+		.map_err(|_| v1::Error::Unauthorized)?;
+
+	Ok(())
+}
+
+/// Returns the session of the auth token, or [`v1::Error::Unauthorized`]
+async fn use_session(
+	state: &mut ServerState,
+	auth_token: Uuid,
+	addr: SocketAddr,
+) -> Result<ActiveSession, Error> {
+	state
+		.db
+		.use_active_session(&hash_token(auth_token), Some(&addr.ip().to_string()))
+		.await?
+		.ok_or(v1::Error::Unauthorized.into())
+}
+
+fn hash_recovery_code(code: &str) -> String {
+	hash_token(totp::normalize_recovery_code(code))
+}
+
+fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+	let salt = SaltString::generate(&mut OsRng);
+
+	Ok(argon2()
+		.hash_password(password.as_bytes(), &salt)?
+		.to_string())
+}
+
+async fn new_auth_session(
+	state: &mut ServerState,
+	user_id: Uuid,
+	client: Option<&ClientInfo>,
+	addr: SocketAddr,
+) -> sqlx::Result<Uuid> {
+	let ip = addr.ip().to_string();
+
+	let token = Uuid::now_v7();
+
+	state
+		.db
+		.insert_active_session(
+			&hash_token(token),
+			user_id,
+			SESSION_LIFETIME,
+			session_metadata(client, &ip),
+		)
+		.await?;
+
+	Ok(token)
+}
+
+pub(super) fn session_metadata<'a>(
+	client: Option<&'a ClientInfo>,
+	ip: &'a str,
+) -> SessionMetadata<'a> {
+	SessionMetadata {
+		client_name: client.map(|c| c.name.as_str()),
+		client_version: client.map(|c| c.version.as_str()),
+		ip: Some(ip),
+	}
 }
 
 fn argon2() -> Argon2<'static> {
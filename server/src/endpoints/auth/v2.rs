@@ -0,0 +1,148 @@
+use super::{
+	SESSION_LIFETIME, hash_token,
+	v1::{
+		Error, check_credentials, complete_two_factor, create_account, session_metadata,
+		start_two_factor,
+	},
+};
+use crate::{ServerState, database::active_sessions::RefreshError};
+use axum::{
+	Json, Router,
+	extract::{ConnectInfo, State},
+	routing::post,
+};
+use protocol::auth::{
+	Request,
+	v2::{self, *},
+};
+use std::net::SocketAddr;
+use tracing::warn;
+use uuid::Uuid;
+
+const ACCESS_TOKEN_LIFETIME: u32 = 15; // in minutes
+
+pub fn routes() -> Router<ServerState> {
+	Router::new()
+		.route(LoginRequest::PATH, post(login))
+		.route(TwoFactorLoginRequest::PATH, post(login_two_factor))
+		.route(FinalizeNewAccountRequest::PATH, post(finalize_new_account))
+		.route(RefreshRequest::PATH, post(refresh))
+}
+
+async fn finalize_new_account(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<FinalizeNewAccountRequest>,
+) -> Result<Json<Tokens>, Error> {
+	let user_id = create_account(
+		&mut state,
+		request.registration_id,
+		&request.username,
+		&request.password,
+	)
+	.await?;
+
+	let tokens =
+		new_refreshable_session(&mut state, user_id, request.client.as_ref(), addr).await?;
+
+	Ok(Json(tokens))
+}
+
+async fn login(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<LoginRequest>,
+) -> Result<Json<LoginResponse>, Error> {
+	let user_id =
+		check_credentials(&mut state, &request.email, &request.password, addr.ip()).await?;
+
+	if let Some(challenge) =
+		start_two_factor(&mut state, user_id, request.client.as_ref(), None).await?
+	{
+		return Ok(Json(LoginResponse::TwoFactorRequired(challenge)));
+	}
+
+	let tokens =
+		new_refreshable_session(&mut state, user_id, request.client.as_ref(), addr).await?;
+
+	Ok(Json(LoginResponse::Success(tokens)))
+}
+
+async fn login_two_factor(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<TwoFactorLoginRequest>,
+) -> Result<Json<Tokens>, Error> {
+	let (user_id, client) =
+		complete_two_factor(&mut state, request.challenge, &request.code, addr.ip()).await?;
+
+	let tokens = new_refreshable_session(&mut state, user_id, client.as_ref(), addr).await?;
+
+	Ok(Json(tokens))
+}
+
+async fn refresh(
+	State(mut state): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<RefreshRequest>,
+) -> Result<Json<Tokens>, Error> {
+	let access_token = Uuid::now_v7();
+	let refresh_token = Uuid::now_v7();
+
+	let session = state
+		.db
+		.rotate_refresh_token(
+			&hash_token(request.refresh_token),
+			&hash_token(access_token),
+			&hash_token(refresh_token),
+			ACCESS_TOKEN_LIFETIME,
+			SESSION_LIFETIME,
+			Some(&addr.ip().to_string()),
+		)
+		.await?;
+
+	match session {
+		Ok(_) => {}
+		Err(RefreshError::Invalid) => return Err(v2::Error::Unauthorized.into()),
+		Err(RefreshError::Reused) => {
+			warn!("refresh token reused from {addr}, session revoked");
+			return Err(v2::Error::Unauthorized.into());
+		}
+	}
+
+	Ok(Json(Tokens {
+		access_token,
+		access_token_expires_in: ACCESS_TOKEN_LIFETIME * 60,
+		refresh_token,
+	}))
+}
+
+async fn new_refreshable_session(
+	state: &mut ServerState,
+	user_id: Uuid,
+	client: Option<&ClientInfo>,
+	addr: SocketAddr,
+) -> sqlx::Result<Tokens> {
+	let ip = addr.ip().to_string();
+
+	let access_token = Uuid::now_v7();
+	let refresh_token = Uuid::now_v7();
+
+	state
+		.db
+		.insert_refreshable_session(
+			&hash_token(access_token),
+			&hash_token(refresh_token),
+			user_id,
+			ACCESS_TOKEN_LIFETIME,
+			SESSION_LIFETIME,
+			session_metadata(client, &ip),
+		)
+		.await?;
+
+	Ok(Tokens {
+		access_token,
+		access_token_expires_in: ACCESS_TOKEN_LIFETIME * 60,
+		refresh_token,
+	})
+}
@@ -0,0 +1,32 @@
+use crate::{
+	ServerState,
+	pages::{
+		internal_error_page,
+		invite::{invite_not_found_page, invite_page},
+	},
+};
+use axum::{
+	extract::{Path, State},
+	response::Html,
+};
+use std::borrow::Cow;
+use tracing::error;
+
+/// Public landing page of an invite link
+pub async fn display_invite(
+	State(mut state): State<ServerState>,
+	Path(code): Path<String>,
+) -> Html<Cow<'static, str>> {
+	let invite = match state.db.valid_chatroom_invite(&code).await {
+		Ok(Some(x)) => x,
+		Ok(None) => {
+			return Html(invite_not_found_page().into());
+		}
+		Err(e) => {
+			error!("error getting chatroom invite ({code}): {e:?}");
+			return Html(internal_error_page().into());
+		}
+	};
+
+	Html(invite_page(&invite.chatroom_name, &invite.code).into())
+}
@@ -1,33 +1,106 @@
 use crate::ServerState;
+use crate::database::chatroom_invites::RedeemInviteError;
+use crate::database::chatroom_members::ChatroomRole;
+use crate::database::message::Message;
+use crate::database::moderation::{ModerationAction, UserNotFound};
+use crate::database::presence::Presence;
+use crate::endpoints::auth::{SESSION_LIFETIME, hash_token};
+use crate::rate_limit::{Action, Key, rate_limit};
 use crate::socket::{RecvError, Socket};
-use crate::update_listener::UpdateSubscriber;
+use crate::update_listener::{
+	ChatEvent, PRESENCE_LIFETIME, PRESENCE_REFRESH_INTERVAL, PresenceEvent, TypingEvent, Update,
+	UpdateSubscriber, UserEvent,
+};
 use anyhow::{Context, Result};
 use axum::{
-	extract::{Path, State, WebSocketUpgrade},
+	extract::{ConnectInfo, Path, State, WebSocketUpgrade},
 	http::StatusCode,
-	response::IntoResponse,
+	response::{IntoResponse, Response},
 };
-use protocol::C2S;
-use protocol::c2s::Authenticate;
-use protocol::s2c::{self, UserInfo};
-use sqlx::postgres::PgListener;
+use chrono::{TimeDelta, Utc};
+use protocol::c2s::{Authenticate, Hello};
+use protocol::s2c::{self, HelloResponse, UserInfo};
+use protocol::{C2S, Capabilities};
+use rand::{Rng, distr::Alphanumeric};
+use std::collections::{BTreeSet, HashMap};
+use std::net::SocketAddr;
 use thiserror::Error;
-use tokio::select;
-use tokio::sync::mpsc::{Receiver, channel};
-use tokio_pubsub::PubSubMessage;
-use tracing::error;
+use tokio::{
+	select,
+	time::{Instant, Interval, interval_at},
+};
+use tracing::{debug, error};
+use unicode_properties::UnicodeEmoji;
 use uuid::Uuid;
 
+const MAX_HISTORY_LIMIT: u32 = 100;
+const MAX_PRESENCE_QUERY: usize = 100;
+const MAX_EMOJI_LEN: usize = 64;
+// combining enclosing keycap
+const KEYCAP: char = '\u{20E3}';
+const MAX_CHATROOM_NAME_LEN: usize = 255;
+const MAX_MUTE_DURATION_SECS: u32 = 365 * 24 * 60 * 60;
+const MAX_INVITE_LIFETIME_SECS: u32 = 30 * 24 * 60 * 60;
+const INVITE_CODE_LEN: usize = 10;
+
+/// Capabilities that the server supports
+const SERVER_CAPABILITIES: Capabilities = Capabilities::MESSAGE_EDITS
+	.union(Capabilities::TYPING)
+	.union(Capabilities::PRESENCE)
+	.union(Capabilities::READ_MARKERS)
+	.union(Capabilities::REACTIONS)
+	.union(Capabilities::CHATROOM_UPDATES)
+	.union(Capabilities::MODERATION);
+
+/// Legacy endpoint, with the exact protocol version in the path and no capabilities
 pub async fn main_endpoint(
 	ws: WebSocketUpgrade,
 	Path(version): Path<u32>,
-	State(mut server): State<ServerState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	State(server): State<ServerState>,
 ) -> impl IntoResponse {
 	match version {
-		protocol::VERSION => Ok(ws.on_upgrade(move |mut socket| async move {
-			let mut socket = Socket::new(&mut socket);
+		protocol::MIN_VERSION..=protocol::VERSION => {
+			Ok(upgrade(ws, server, addr, Handshake::Fixed(version)))
+		}
+		other => Err((
+			StatusCode::NOT_IMPLEMENTED,
+			format!(
+				"Protocol version v{other} not supported. Server running v{}",
+				protocol::VERSION
+			),
+		)),
+	}
+}
+
+/// Endpoint where the protocol version and capabilities are negotiated with [`Hello`]
+pub async fn negotiated_endpoint(
+	ws: WebSocketUpgrade,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	State(server): State<ServerState>,
+) -> impl IntoResponse {
+	upgrade(ws, server, addr, Handshake::Negotiate)
+}
+
+enum Handshake {
+	Fixed(u32),
+	Negotiate,
+}
 
-			if let Err(e) = handle_socket(&mut server, &mut socket).await {
+fn upgrade(
+	ws: WebSocketUpgrade,
+	mut server: ServerState,
+	addr: SocketAddr,
+	handshake: Handshake,
+) -> Response {
+	ws.on_upgrade(move |mut socket| async move {
+		let mut socket = Socket::new(&mut socket);
+
+		match handle_socket(&mut server, &mut socket, addr, handshake).await {
+			Ok(()) => {
+				let _ = socket.close().await;
+			}
+			Err(e) => {
 				error!("{e}");
 
 				let error_to_send_client: s2c::Error = match e {
@@ -38,21 +111,26 @@ pub async fn main_endpoint(
 				let _ = socket.send_packet(error_to_send_client).await;
 				let _ = socket.close().await;
 			}
-		})),
-		other => Err((
-			StatusCode::NOT_IMPLEMENTED,
-			format!(
-				"Protocol version v{other} not supported. Server running v{}",
-				protocol::VERSION
-			),
-		)),
-	}
+		}
+	})
 }
 
 struct ConnectionState {
 	user_id: Uuid,
+	// the auth session, the connection is closed when it gets revoked
+	session_id: Uuid,
+	protocol_version: u32,
+	capabilities: Capabilities,
 	last_msg_seq_id: Option<i64>,
 	update_subscriber: UpdateSubscriber,
+	// chatrooms where the user is currently typing
+	typing_in: BTreeSet<Uuid>,
+	// identifies this connection in the presence of the user
+	socket_id: Uuid,
+	away: bool,
+	presence_refresh: Interval,
+	// index of the packet currently being handled, used in rejections
+	packet_index: u64,
 }
 
 #[derive(Error, Debug)]
@@ -102,19 +180,39 @@ impl From<Error> for s2c::Error {
 	}
 }
 
-async fn handle_socket(server: &mut ServerState, socket: &mut Socket<'_>) -> Result<(), Error> {
-	// first and foremost we are waiting for the Authenticate packet
+async fn handle_socket(
+	server: &mut ServerState,
+	socket: &mut Socket<'_>,
+	addr: SocketAddr,
+	handshake: Handshake,
+) -> Result<(), Error> {
+	let (protocol_version, capabilities) = match handshake {
+		Handshake::Fixed(version) => (version, Capabilities::NONE),
+		Handshake::Negotiate => match negotiate(socket).await? {
+			Some(x) => x,
+			None => return Ok(()),
+		},
+	};
+	socket.set_protocol_version(protocol_version);
+
+	// then we are waiting for the Authenticate packet
 	let auth: Authenticate = socket.recv().await?;
 	let token = Uuid::from_bytes(auth.auth_token);
 
-	let user = server
+	let session = server
 		.db
-		.user_by_auth_token(token)
+		.use_active_session(&hash_token(token), Some(&addr.ip().to_string()))
 		.await?
 		.ok_or(Error::Unauthorized)?;
 
+	let user = server
+		.db
+		.user_by_id(session.user_id)
+		.await?
+		.context("user of session doesnt exist")?;
+
 	socket
-		.send_packet(UserInfo {
+		.send_state_packet(UserInfo {
 			username: user.username,
 		})
 		.await?;
@@ -123,18 +221,106 @@ async fn handle_socket(server: &mut ServerState, socket: &mut Socket<'_>) -> Res
 
 	let mut state = ConnectionState {
 		user_id: user.id,
+		session_id: session.id,
+		protocol_version,
+		capabilities,
 		last_msg_seq_id: None,
 		update_subscriber: server.updates.subscribe().await,
+		typing_in: BTreeSet::new(),
+		socket_id: Uuid::now_v7(),
+		away: false,
+		presence_refresh: interval_at(
+			Instant::now() + PRESENCE_REFRESH_INTERVAL,
+			PRESENCE_REFRESH_INTERVAL,
+		),
+		packet_index: 0,
 	};
 
+	debug!(
+		"user {} connected using protocol v{} with {:?}",
+		state.user_id, state.protocol_version, state.capabilities
+	);
+
+	server
+		.db
+		.upsert_presence_socket(state.socket_id, state.user_id, false, PRESENCE_LIFETIME)
+		.await?;
+
+	let result = run_connection(server, &mut state, socket).await;
+
+	// the socket is gone whatever the reason was
+	if let Err(e) = server.db.delete_presence_socket(state.socket_id).await {
+		error!("couldnt delete presence of socket: {e}");
+	}
+
+	result
+}
+
+async fn run_connection(
+	server: &mut ServerState,
+	state: &mut ConnectionState,
+	socket: &mut Socket<'_>,
+) -> Result<(), Error> {
 	state
 		.update_subscriber
-		.subscribe_chat(Uuid::from_u128(5))
+		.subscribe_user_events(state.user_id)
 		.await
 		.unwrap();
+
+	// the session could have been revoked before subscribing
+	if !server.db.is_session_active(state.session_id).await? {
+		return Err(Error::Unauthorized);
+	}
+
+	if server.config.sliding_session_expiry {
+		server
+			.db
+			.extend_active_session(state.session_id, SESSION_LIFETIME)
+			.await?;
+	}
+
+	if state.capabilities.contains(Capabilities::PRESENCE) {
+		for (chatroom, user_id) in server.db.chatroom_co_members(state.user_id).await? {
+			state
+				.update_subscriber
+				.subscribe_presence(chatroom, user_id)
+				.await;
+		}
+	}
+
 	loop {
-		next_event(server, &mut state, socket).await?;
+		next_event(server, state, socket).await?;
+	}
+}
+
+/// Returns the negotiated protocol version and capabilities,
+/// or `None` if there is no version supported by both sides
+async fn negotiate(socket: &mut Socket<'_>) -> Result<Option<(u32, Capabilities)>, Error> {
+	let hello: Hello = socket.recv().await?;
+
+	let version = hello.max_version.min(protocol::VERSION);
+
+	if version < hello.min_version.max(protocol::MIN_VERSION) {
+		socket
+			.send_state_packet(HelloResponse::Unsupported {
+				min_version: protocol::MIN_VERSION,
+				max_version: protocol::VERSION,
+			})
+			.await?;
+
+		return Ok(None);
 	}
+
+	let capabilities = hello.capabilities & SERVER_CAPABILITIES;
+
+	socket
+		.send_state_packet(HelloResponse::Accepted {
+			version,
+			capabilities,
+		})
+		.await?;
+
+	Ok(Some((version, capabilities)))
 }
 
 async fn next_event(
@@ -143,24 +329,328 @@ async fn next_event(
 	socket: &mut Socket<'_>,
 ) -> Result<(), Error> {
 	select! {
-		packet = socket.recv() => {
+		packet = socket.recv_packet() => {
 			handle_packet(server, state, socket, packet?).await?;
+			state.packet_index += 1;
 		}
-		msg = state.update_subscriber.recv_chat_messages() => {
-			let msg = msg?;
+		update = state.update_subscriber.recv() => {
+			match update? {
+				Update::Chat(event) => handle_chat_event(server, state, socket, &event).await?,
+				Update::Typing(event) => handle_typing_event(server, state, socket, &event).await?,
+				Update::Presence(event) => handle_presence_event(state, socket, &event).await?,
+				Update::User(event) => handle_user_event(server, state, socket, &event).await?,
+			}
+		},
+		_ = state.presence_refresh.tick() => {
+			// the session could have expired, or its revocation got lost without a disruption being noticed
+			if !server.db.is_session_active(state.session_id).await? {
+				return Err(Error::Unauthorized);
+			}
 
-			state.last_msg_seq_id = Some(msg.sequence_id);
+			server
+				.db
+				.upsert_presence_socket(state.socket_id, state.user_id, state.away, PRESENCE_LIFETIME)
+				.await?;
 
-			socket.send_packet(s2c::NewMessage{
-				user: msg.user_id.to_string(),
-				message: msg.message.clone(),
-			}).await?;
+			// an open connection counts as using the session
+			if server.config.sliding_session_expiry {
+				server
+					.db
+					.extend_active_session(state.session_id, SESSION_LIFETIME)
+					.await?;
+			}
 		},
 	}
 
 	Ok(())
 }
 
+async fn handle_chat_event(
+	server: &mut ServerState,
+	state: &mut ConnectionState,
+	socket: &mut Socket<'_>,
+	event: &ChatEvent,
+) -> Result<(), Error> {
+	match event {
+		ChatEvent::NewMessage(msg) => {
+			state.last_msg_seq_id = Some(msg.sequence_id);
+
+			let msg = chat_message(server, msg).await?;
+
+			socket.send_packet(msg).await?;
+		}
+		ChatEvent::MessageEdited {
+			id,
+			chatroom,
+			sequence_id,
+			change_id,
+			message,
+			edited_at,
+		} => {
+			if state.capabilities.contains(Capabilities::MESSAGE_EDITS) {
+				socket
+					.send_packet(s2c::MessageEdited {
+						id: *id.as_bytes(),
+						chatroom: *chatroom.as_bytes(),
+						sequence_id: *sequence_id,
+						change_id: *change_id,
+						message: message.clone(),
+						edited_at: edited_at.timestamp_millis(),
+					})
+					.await?;
+			}
+		}
+		ChatEvent::MessageDeleted {
+			id,
+			chatroom,
+			sequence_id,
+			change_id,
+			deleted_at,
+		} => {
+			if state.capabilities.contains(Capabilities::MESSAGE_EDITS) {
+				socket
+					.send_packet(s2c::MessageDeleted {
+						id: *id.as_bytes(),
+						chatroom: *chatroom.as_bytes(),
+						sequence_id: *sequence_id,
+						change_id: *change_id,
+						deleted_at: deleted_at.timestamp_millis(),
+					})
+					.await?;
+			}
+		}
+		ChatEvent::ReactionUpdated {
+			message_id,
+			chatroom,
+			sequence_id,
+			change_id,
+			user_id,
+			emoji,
+			added,
+			count,
+		} => {
+			if state.capabilities.contains(Capabilities::REACTIONS) {
+				socket
+					.send_packet(s2c::ReactionUpdated {
+						message_id: *message_id.as_bytes(),
+						chatroom: *chatroom.as_bytes(),
+						sequence_id: *sequence_id,
+						change_id: *change_id,
+						user_id: *user_id.as_bytes(),
+						emoji: emoji.clone(),
+						added: *added,
+						count: *count as u32,
+					})
+					.await?;
+			}
+		}
+		ChatEvent::MessageChanged(msg) => {
+			if state.capabilities.contains(Capabilities::MESSAGE_EDITS)
+				|| state.capabilities.contains(Capabilities::REACTIONS)
+			{
+				let change_id = msg
+					.change_id
+					.context("changed message without a change id")?;
+				let message =
+					chat_messages_with_reactions(server, state, std::slice::from_ref(msg))
+						.await?
+						.pop()
+						.unwrap();
+
+				socket
+					.send_packet(s2c::MessageChanged { change_id, message })
+					.await?;
+			}
+		}
+	}
+
+	Ok(())
+}
+
+async fn handle_typing_event(
+	server: &mut ServerState,
+	state: &mut ConnectionState,
+	socket: &mut Socket<'_>,
+	event: &TypingEvent,
+) -> Result<(), Error> {
+	// users dont need to see themselves typing
+	if event.user_id == state.user_id {
+		return Ok(());
+	}
+
+	let username = server
+		.usernames
+		.get(event.user_id)
+		.await?
+		.with_context(|| format!("typing user {} doesnt exist", event.user_id))?;
+
+	socket
+		.send_packet(s2c::TypingUpdate {
+			chatroom: *event.chatroom.as_bytes(),
+			user_id: *event.user_id.as_bytes(),
+			username: username.to_string(),
+			typing: event.typing,
+		})
+		.await?;
+
+	Ok(())
+}
+
+async fn handle_presence_event(
+	state: &mut ConnectionState,
+	socket: &mut Socket<'_>,
+	event: &PresenceEvent,
+) -> Result<(), Error> {
+	if !state.capabilities.contains(Capabilities::PRESENCE) {
+		return Ok(());
+	}
+
+	socket
+		.send_packet(s2c::PresenceChanged {
+			user_id: *event.user_id.as_bytes(),
+			presence: presence_to_s2c(event.presence),
+		})
+		.await?;
+
+	Ok(())
+}
+
+async fn handle_user_event(
+	server: &mut ServerState,
+	state: &mut ConnectionState,
+	socket: &mut Socket<'_>,
+	event: &UserEvent,
+) -> Result<(), Error> {
+	match event {
+		UserEvent::ReadMarker {
+			chatroom,
+			last_read_seq_id,
+		} => {
+			if state.capabilities.contains(Capabilities::READ_MARKERS) {
+				socket
+					.send_packet(s2c::ReadMarkerUpdated {
+						chatroom: *chatroom.as_bytes(),
+						last_read_seq_id: *last_read_seq_id,
+					})
+					.await?;
+			}
+		}
+		UserEvent::ChatroomAdded {
+			chatroom,
+			name,
+			archived,
+			last_seq_id,
+			direct_with,
+		} => {
+			// new co-members
+			if state.capabilities.contains(Capabilities::PRESENCE) {
+				subscribe_members_presence(server, state, *chatroom).await?;
+			}
+
+			if !state.capabilities.contains(Capabilities::CHATROOM_UPDATES) {
+				return Ok(());
+			}
+
+			let change = match direct_with {
+				Some(user_id) => {
+					let username = server
+						.usernames
+						.get(*user_id)
+						.await?
+						.with_context(|| format!("direct chat user {user_id} doesnt exist"))?;
+
+					s2c::ChatroomListChanged::DirectChatAdded(s2c::DirectChatInfo {
+						chatroom: *chatroom.as_bytes(),
+						user_id: *user_id.as_bytes(),
+						username: username.to_string(),
+						last_seq_id: *last_seq_id,
+					})
+				}
+				None => s2c::ChatroomListChanged::Added(s2c::ChatroomInfo {
+					id: *chatroom.as_bytes(),
+					name: name.clone(),
+					archived: *archived,
+					last_seq_id: *last_seq_id,
+				}),
+			};
+
+			socket.send_packet(change).await?;
+		}
+		UserEvent::ChatroomUpdated {
+			chatroom,
+			name,
+			archived,
+		} => {
+			if state.capabilities.contains(Capabilities::CHATROOM_UPDATES) {
+				socket
+					.send_packet(s2c::ChatroomListChanged::Updated {
+						id: *chatroom.as_bytes(),
+						name: name.clone(),
+						archived: *archived,
+					})
+					.await?;
+			}
+		}
+		UserEvent::ChatroomRemoved { chatroom } => {
+			remove_chatroom(server, state, socket, *chatroom).await?;
+		}
+		UserEvent::MemberAdded { chatroom, user_id } => {
+			if state.capabilities.contains(Capabilities::PRESENCE) {
+				state
+					.update_subscriber
+					.subscribe_presence(*chatroom, *user_id)
+					.await;
+			}
+		}
+		UserEvent::MemberRemoved { chatroom, user_id } => {
+			state
+				.update_subscriber
+				.unsubscribe_presence(*chatroom, *user_id)
+				.await;
+		}
+		UserEvent::Resync => {
+			// the revocation of this session might have been missed
+			if !server.db.is_session_active(state.session_id).await? {
+				return Err(Error::Unauthorized);
+			}
+
+			// same with removals from chatrooms
+			for chatroom in state.update_subscriber.subscribed_chats() {
+				if !server
+					.db
+					.is_chatroom_member(chatroom, state.user_id)
+					.await?
+				{
+					remove_chatroom(server, state, socket, chatroom).await?;
+				}
+			}
+		}
+		UserEvent::SessionRevoked { session_id } => {
+			if *session_id == state.session_id {
+				return Err(Error::Unauthorized);
+			}
+		}
+		UserEvent::MembershipUpdated {
+			chatroom,
+			role,
+			muted_until,
+		} => {
+			if state.capabilities.contains(Capabilities::MODERATION) {
+				socket
+					.send_packet(s2c::MembershipUpdated {
+						chatroom: *chatroom.as_bytes(),
+						role: role_to_s2c(*role),
+						muted_until: muted_until.map(|t| t.timestamp_millis()),
+					})
+					.await?;
+			}
+		}
+		UserEvent::Unknown => {}
+	}
+
+	Ok(())
+}
+
 async fn handle_packet(
 	server: &mut ServerState,
 	state: &mut ConnectionState,
@@ -168,13 +658,1102 @@ async fn handle_packet(
 	packet: C2S,
 ) -> Result<(), Error> {
 	match packet {
+		C2S::JoinChat(join_chat) => {
+			let chatroom = Uuid::from_bytes(join_chat.chatroom);
+
+			if !server
+				.db
+				.is_chatroom_member(chatroom, state.user_id)
+				.await?
+			{
+				reject(socket, state, s2c::Error::NotChatroomMember).await?;
+				return Ok(());
+			}
+
+			// joining an already joined chatroom is a no-op
+			let _ = state
+				.update_subscriber
+				.subscribe_chat(
+					chatroom,
+					join_chat.last_seen_seq_id,
+					join_chat.last_seen_change_id,
+				)
+				.await?;
+
+			if state.capabilities.contains(Capabilities::TYPING) {
+				let _ = state.update_subscriber.subscribe_typing(chatroom).await;
+			}
+		}
+		C2S::LeaveChat(leave_chat) => {
+			let chatroom = Uuid::from_bytes(leave_chat.chatroom);
+
+			// same with leaving a chatroom that wasnt joined
+			unsubscribe_chatroom(server, state, chatroom).await?;
+		}
 		C2S::SendMessage(send_message) => {
+			let chatroom = Uuid::from_bytes(send_message.chatroom);
+			let nonce = send_message.nonce;
+
+			// retries of already stored messages are acknowledged again without counting towards the rate limit
+			if let Some((message_id, sequence_id)) = server
+				.db
+				.message_by_nonce(chatroom, state.user_id, Uuid::from_bytes(nonce))
+				.await?
+			{
+				socket
+					.send_packet(s2c::MessageAck {
+						nonce,
+						message_id: *message_id.as_bytes(),
+						sequence_id,
+					})
+					.await?;
+				return Ok(());
+			}
+
+			if let Err(limited) =
+				rate_limit(server, Action::SendMessage, &[Key::User(state.user_id)]).await?
+			{
+				reject_message(
+					socket,
+					state,
+					nonce,
+					s2c::Error::RateLimited {
+						retry_after: limited.retry_after_secs(),
+					},
+				)
+				.await?;
+				return Ok(());
+			}
+
+			match server.db.chatroom_member(chatroom, state.user_id).await? {
+				Some(member) if member.is_muted() => {
+					reject_message(socket, state, nonce, s2c::Error::Muted).await?;
+					return Ok(());
+				}
+				Some(_) => {}
+				None => {
+					reject_message(socket, state, nonce, s2c::Error::NotChatroomMember).await?;
+					return Ok(());
+				}
+			}
+
+			if is_chatroom_archived(server, chatroom).await? {
+				reject_message(socket, state, nonce, s2c::Error::ChatroomArchived).await?;
+				return Ok(());
+			}
+
+			let reply_to = send_message.reply_to.map(Uuid::from_bytes);
+
+			if let Some(reply_to) = reply_to {
+				match server.db.message_by_id(reply_to).await? {
+					Some(msg) if msg.chatroom == chatroom && msg.deleted_at.is_none() => {}
+					_ => {
+						reject_message(socket, state, nonce, s2c::Error::MessageNotFound).await?;
+						return Ok(());
+					}
+				}
+			}
+
+			let (message_id, sequence_id) = server
+				.db
+				.insert_message(
+					chatroom,
+					state.user_id,
+					&send_message.message,
+					Uuid::from_bytes(nonce),
+					reply_to,
+				)
+				.await?;
+
+			// the message was sent, so the user is no longer typing it
+			if state.typing_in.remove(&chatroom) {
+				server
+					.db
+					.notify_typing(chatroom, state.user_id, false)
+					.await?;
+			}
+
+			socket
+				.send_packet(s2c::MessageAck {
+					nonce,
+					message_id: *message_id.as_bytes(),
+					sequence_id,
+				})
+				.await?;
+		}
+		C2S::FetchHistory(fetch_history) => {
+			let chatroom = Uuid::from_bytes(fetch_history.chatroom);
+
+			if !server
+				.db
+				.is_chatroom_member(chatroom, state.user_id)
+				.await?
+			{
+				reject(socket, state, s2c::Error::NotChatroomMember).await?;
+				return Ok(());
+			}
+
+			let messages = server
+				.db
+				.messages_before_seq_id(
+					&chatroom,
+					fetch_history.before_seq_id,
+					fetch_history.limit.min(MAX_HISTORY_LIMIT),
+				)
+				.await?;
+
+			let history = chat_messages_with_reactions(server, state, &messages).await?;
+
+			socket
+				.send_packet(s2c::History {
+					chatroom: fetch_history.chatroom,
+					messages: history,
+				})
+				.await?;
+		}
+		C2S::EditMessage(edit_message) => {
+			let message_id = Uuid::from_bytes(edit_message.message_id);
+
+			if let Some(error) = check_message_author(server, state, message_id).await? {
+				reject(socket, state, error).await?;
+				return Ok(());
+			}
+
+			// could have been deleted in the meantime
+			if !server
+				.db
+				.edit_message(message_id, &edit_message.message)
+				.await?
+			{
+				reject(socket, state, s2c::Error::MessageNotFound).await?;
+			}
+		}
+		C2S::DeleteMessage(delete_message) => {
+			let message_id = Uuid::from_bytes(delete_message.message_id);
+
+			if let Some(error) = check_message_author(server, state, message_id).await? {
+				reject(socket, state, error).await?;
+				return Ok(());
+			}
+
+			if !server.db.delete_message(message_id).await? {
+				reject(socket, state, s2c::Error::MessageNotFound).await?;
+			}
+		}
+		C2S::Typing(typing) => {
+			let chatroom = Uuid::from_bytes(typing.chatroom);
+
+			if !state.capabilities.contains(Capabilities::TYPING) {
+				reject(socket, state, s2c::Error::InvalidPacket).await?;
+				return Ok(());
+			}
+
+			// membership was already checked when joining
+			if !state.update_subscriber.is_chat_subscribed(chatroom) {
+				reject(socket, state, s2c::Error::ChatNotJoined).await?;
+				return Ok(());
+			}
+
+			if typing.typing {
+				state.typing_in.insert(chatroom);
+			} else if !state.typing_in.remove(&chatroom) {
+				// wasnt typing
+				return Ok(());
+			}
+
+			server
+				.db
+				.notify_typing(chatroom, state.user_id, typing.typing)
+				.await?;
+		}
+		C2S::SetAway(set_away) => {
+			state.away = set_away.away;
+
 			server
 				.db
-				.insert_message(Uuid::from_u128(5), state.user_id, &send_message.message)
+				.upsert_presence_socket(
+					state.socket_id,
+					state.user_id,
+					state.away,
+					PRESENCE_LIFETIME,
+				)
+				.await?;
+		}
+		C2S::QueryPresence(query_presence) => {
+			let users: Vec<Uuid> = query_presence
+				.users
+				.iter()
+				.take(MAX_PRESENCE_QUERY)
+				.map(|id| Uuid::from_bytes(*id))
+				.collect();
+
+			let presence = server
+				.db
+				.visible_users_presence(state.user_id, &users)
+				.await?;
+
+			socket
+				.send_packet(s2c::PresenceInfo {
+					users: presence
+						.into_iter()
+						.map(|(user_id, presence)| s2c::UserPresence {
+							user_id: *user_id.as_bytes(),
+							presence: presence_to_s2c(presence),
+						})
+						.collect(),
+				})
 				.await?;
 		}
+		C2S::FetchThread(fetch_thread) => {
+			let thread_root = Uuid::from_bytes(fetch_thread.thread_root);
+
+			// the root can be deleted, the replies are still there.
+			// non-members get the same error so they can't probe for message ids
+			let chatroom = match server.db.message_by_id(thread_root).await? {
+				Some(msg)
+					if server
+						.db
+						.is_chatroom_member(msg.chatroom, state.user_id)
+						.await? =>
+				{
+					msg.chatroom
+				}
+				_ => {
+					reject(socket, state, s2c::Error::MessageNotFound).await?;
+					return Ok(());
+				}
+			};
+
+			let messages = server
+				.db
+				.thread_messages_before_seq_id(
+					&thread_root,
+					fetch_thread.before_seq_id,
+					fetch_thread.limit.min(MAX_HISTORY_LIMIT),
+				)
+				.await?;
+
+			let messages = chat_messages_with_reactions(server, state, &messages).await?;
+
+			socket
+				.send_packet(s2c::Thread {
+					chatroom: *chatroom.as_bytes(),
+					thread_root: fetch_thread.thread_root,
+					messages,
+				})
+				.await?;
+		}
+		C2S::OpenDirectChat(open_direct_chat) => {
+			let other = match server
+				.db
+				.user_by_username(&open_direct_chat.username)
+				.await?
+			{
+				Some(x) => x,
+				None => {
+					reject(socket, state, s2c::Error::UserNotFound).await?;
+					return Ok(());
+				}
+			};
+
+			if other.id == state.user_id {
+				reject(socket, state, s2c::Error::DirectChatWithSelf).await?;
+				return Ok(());
+			}
+
+			let chatroom = server.db.open_direct_chat(state.user_id, other.id).await?;
+			let last_seq_id = server.db.fetch_last_message_seq_id(&chatroom).await?;
+
+			socket
+				.send_packet(s2c::DirectChatOpened(s2c::DirectChatInfo {
+					chatroom: *chatroom.as_bytes(),
+					user_id: *other.id.as_bytes(),
+					username: other.username,
+					last_seq_id: (last_seq_id >= 0).then_some(last_seq_id),
+				}))
+				.await?;
+		}
+		C2S::ListChatrooms(_) => {
+			let groups = server.db.user_group_chatrooms(state.user_id).await?;
+			let direct_chats = server.db.user_direct_chats(state.user_id).await?;
+
+			socket
+				.send_packet(s2c::Chatrooms {
+					groups: groups
+						.into_iter()
+						.map(|chatroom| s2c::ChatroomInfo {
+							id: *chatroom.id.as_bytes(),
+							name: chatroom.name,
+							archived: chatroom.archived,
+							last_seq_id: chatroom.last_seq_id,
+						})
+						.collect(),
+					direct_chats: direct_chats
+						.into_iter()
+						.map(|direct_chat| s2c::DirectChatInfo {
+							chatroom: *direct_chat.chatroom.as_bytes(),
+							user_id: *direct_chat.user_id.unwrap_or(Uuid::nil()).as_bytes(),
+							username: direct_chat.username.unwrap_or_default(),
+							last_seq_id: direct_chat.last_seq_id,
+						})
+						.collect(),
+				})
+				.await?;
+		}
+		C2S::CreateChatroom(create_chatroom) => {
+			let name = match validate_chatroom_name(&create_chatroom.name) {
+				Some(x) => x,
+				None => {
+					reject(socket, state, s2c::Error::InvalidChatroomName).await?;
+					return Ok(());
+				}
+			};
+
+			let chatroom = server.db.create_chatroom(name, state.user_id).await?;
+
+			socket
+				.send_packet(s2c::ChatroomCreated(s2c::ChatroomInfo {
+					id: *chatroom.as_bytes(),
+					name: name.to_owned(),
+					archived: false,
+					last_seq_id: None,
+				}))
+				.await?;
+		}
+		C2S::RenameChatroom(rename_chatroom) => {
+			let chatroom = Uuid::from_bytes(rename_chatroom.chatroom);
+
+			let name = match validate_chatroom_name(&rename_chatroom.name) {
+				Some(x) => x,
+				None => {
+					reject(socket, state, s2c::Error::InvalidChatroomName).await?;
+					return Ok(());
+				}
+			};
+
+			if let Some(error) = check_chatroom_owner(server, state, chatroom).await? {
+				reject(socket, state, error).await?;
+				return Ok(());
+			}
+
+			server.db.rename_chatroom(chatroom, name).await?;
+		}
+		C2S::SetChatroomArchived(set_archived) => {
+			let chatroom = Uuid::from_bytes(set_archived.chatroom);
+
+			if let Some(error) = check_chatroom_owner(server, state, chatroom).await? {
+				reject(socket, state, error).await?;
+				return Ok(());
+			}
+
+			server
+				.db
+				.set_chatroom_archived(chatroom, set_archived.archived)
+				.await?;
+		}
+		C2S::DeleteChatroom(delete_chatroom) => {
+			let chatroom = Uuid::from_bytes(delete_chatroom.chatroom);
+
+			if let Some(error) = check_chatroom_owner(server, state, chatroom).await? {
+				reject(socket, state, error).await?;
+				return Ok(());
+			}
+
+			// members are notified through their removed memberships
+			server.db.delete_chatroom(chatroom).await?;
+		}
+		C2S::FetchChatroomMembers(fetch_members) => {
+			let chatroom = Uuid::from_bytes(fetch_members.chatroom);
+
+			if !server
+				.db
+				.is_chatroom_member(chatroom, state.user_id)
+				.await?
+			{
+				reject(socket, state, s2c::Error::NotChatroomMember).await?;
+				return Ok(());
+			}
+
+			let members = server
+				.db
+				.chatroom_members(chatroom)
+				.await?
+				.into_iter()
+				.map(|member| s2c::ChatroomMemberInfo {
+					user_id: *member.user_id.as_bytes(),
+					role: role_to_s2c(member.role),
+					muted_until: member
+						.muted_until
+						.filter(|_| member.is_muted())
+						.map(|t| t.timestamp_millis()),
+					username: member.username,
+				})
+				.collect();
+
+			socket
+				.send_packet(s2c::ChatroomMembers {
+					chatroom: fetch_members.chatroom,
+					members,
+				})
+				.await?;
+		}
+		C2S::SetMemberRole(set_role) => {
+			let chatroom = Uuid::from_bytes(set_role.chatroom);
+			let target = Uuid::from_bytes(set_role.user_id);
+			let role = role_from_s2c(set_role.role);
+
+			if let Some(error) = check_chatroom_owner(server, state, chatroom).await? {
+				reject(socket, state, error).await?;
+				return Ok(());
+			}
+
+			if target == state.user_id {
+				reject(socket, state, s2c::Error::NotPermitted).await?;
+				return Ok(());
+			}
+
+			if !server.db.is_chatroom_member(chatroom, target).await? {
+				reject(socket, state, s2c::Error::UserNotFound).await?;
+				return Ok(());
+			}
+
+			let mut transaction = server.db.transaction().await?;
+
+			transaction
+				.set_chatroom_member_role(chatroom, target, role)
+				.await?;
+
+			// there is only a single owner
+			if role == ChatroomRole::Owner {
+				transaction
+					.set_chatroom_member_role(chatroom, state.user_id, ChatroomRole::Moderator)
+					.await?;
+			}
+
+			transaction
+				.log_moderation_action(
+					chatroom,
+					state.user_id,
+					target,
+					ModerationAction::SetRole(role),
+				)
+				.await?;
+
+			transaction.commit().await?;
+		}
+		C2S::KickMember(kick_member) => {
+			let chatroom = Uuid::from_bytes(kick_member.chatroom);
+			let target = Uuid::from_bytes(kick_member.user_id);
+
+			if let Some(error) = check_moderator(server, state, chatroom, target).await? {
+				reject(socket, state, error).await?;
+				return Ok(());
+			}
+
+			if !server.db.is_chatroom_member(chatroom, target).await? {
+				reject(socket, state, s2c::Error::UserNotFound).await?;
+				return Ok(());
+			}
+
+			// the live sockets of the target are unsubscribed through the removed membership
+			let mut transaction = server.db.transaction().await?;
+
+			transaction.remove_chatroom_member(chatroom, target).await?;
+			transaction
+				.log_moderation_action(chatroom, state.user_id, target, ModerationAction::Kick)
+				.await?;
+
+			transaction.commit().await?;
+		}
+		C2S::BanMember(ban_member) => {
+			let chatroom = Uuid::from_bytes(ban_member.chatroom);
+			let target = Uuid::from_bytes(ban_member.user_id);
+
+			if let Some(error) = check_moderator(server, state, chatroom, target).await? {
+				reject(socket, state, error).await?;
+				return Ok(());
+			}
+
+			let mut transaction = server.db.transaction().await?;
+
+			if let Err(UserNotFound) = transaction
+				.ban_from_chatroom(chatroom, target, state.user_id)
+				.await?
+			{
+				reject(socket, state, s2c::Error::UserNotFound).await?;
+				return Ok(());
+			}
+			transaction.remove_chatroom_member(chatroom, target).await?;
+			transaction
+				.log_moderation_action(chatroom, state.user_id, target, ModerationAction::Ban)
+				.await?;
+
+			transaction.commit().await?;
+		}
+		C2S::UnbanMember(unban_member) => {
+			let chatroom = Uuid::from_bytes(unban_member.chatroom);
+			let target = Uuid::from_bytes(unban_member.user_id);
+
+			if let Some(error) = check_moderator(server, state, chatroom, target).await? {
+				reject(socket, state, error).await?;
+				return Ok(());
+			}
+
+			let mut transaction = server.db.transaction().await?;
+
+			// nothing to log if the user wasnt banned
+			if transaction.unban_from_chatroom(chatroom, target).await? {
+				transaction
+					.log_moderation_action(chatroom, state.user_id, target, ModerationAction::Unban)
+					.await?;
+			}
+
+			transaction.commit().await?;
+		}
+		C2S::MuteMember(mute_member) => {
+			let chatroom = Uuid::from_bytes(mute_member.chatroom);
+			let target = Uuid::from_bytes(mute_member.user_id);
+
+			if !(1..=MAX_MUTE_DURATION_SECS).contains(&mute_member.duration_secs) {
+				reject(socket, state, s2c::Error::InvalidMuteDuration).await?;
+				return Ok(());
+			}
+
+			if let Some(error) = check_moderator(server, state, chatroom, target).await? {
+				reject(socket, state, error).await?;
+				return Ok(());
+			}
+
+			if !server.db.is_chatroom_member(chatroom, target).await? {
+				reject(socket, state, s2c::Error::UserNotFound).await?;
+				return Ok(());
+			}
+
+			let until = Utc::now() + TimeDelta::seconds(mute_member.duration_secs.into());
+
+			let mut transaction = server.db.transaction().await?;
+
+			transaction
+				.set_chatroom_member_muted_until(chatroom, target, Some(until))
+				.await?;
+			transaction
+				.log_moderation_action(
+					chatroom,
+					state.user_id,
+					target,
+					ModerationAction::Mute { until },
+				)
+				.await?;
+
+			transaction.commit().await?;
+		}
+		C2S::UnmuteMember(unmute_member) => {
+			let chatroom = Uuid::from_bytes(unmute_member.chatroom);
+			let target = Uuid::from_bytes(unmute_member.user_id);
+
+			if let Some(error) = check_moderator(server, state, chatroom, target).await? {
+				reject(socket, state, error).await?;
+				return Ok(());
+			}
+
+			if !server.db.is_chatroom_member(chatroom, target).await? {
+				reject(socket, state, s2c::Error::UserNotFound).await?;
+				return Ok(());
+			}
+
+			let mut transaction = server.db.transaction().await?;
+
+			transaction
+				.set_chatroom_member_muted_until(chatroom, target, None)
+				.await?;
+			transaction
+				.log_moderation_action(chatroom, state.user_id, target, ModerationAction::Unmute)
+				.await?;
+
+			transaction.commit().await?;
+		}
+		C2S::CreateInvite(create_invite) => {
+			let chatroom = Uuid::from_bytes(create_invite.chatroom);
+
+			let valid_lifetime = create_invite
+				.expires_in_secs
+				.is_none_or(|secs| (1..=MAX_INVITE_LIFETIME_SECS).contains(&secs));
+			let valid_max_uses = create_invite
+				.max_uses
+				.is_none_or(|uses| (1..=i32::MAX as u32).contains(&uses));
+
+			if !valid_lifetime || !valid_max_uses {
+				reject(socket, state, s2c::Error::InvalidInviteOptions).await?;
+				return Ok(());
+			}
+
+			match server.db.chatroom_member(chatroom, state.user_id).await? {
+				Some(member) if member.role >= ChatroomRole::Moderator => {}
+				Some(_) => {
+					reject(socket, state, s2c::Error::NotPermitted).await?;
+					return Ok(());
+				}
+				None => {
+					reject(socket, state, s2c::Error::NotChatroomMember).await?;
+					return Ok(());
+				}
+			}
+
+			let code: String = rand::rng()
+				.sample_iter(Alphanumeric)
+				.take(INVITE_CODE_LEN)
+				.map(char::from)
+				.collect();
+			let expires_at = create_invite
+				.expires_in_secs
+				.map(|secs| Utc::now() + TimeDelta::seconds(secs.into()));
+
+			server
+				.db
+				.insert_chatroom_invite(
+					&code,
+					chatroom,
+					state.user_id,
+					expires_at,
+					create_invite.max_uses.map(|uses| uses as i32),
+				)
+				.await?;
+
+			let link = server
+				.config
+				.public_base_url
+				.join("invite/")
+				.unwrap()
+				.join(&code)
+				.unwrap();
+
+			socket
+				.send_packet(s2c::InviteCreated {
+					chatroom: create_invite.chatroom,
+					code,
+					link: link.into(),
+					expires_at: expires_at.map(|t| t.timestamp_millis()),
+					max_uses: create_invite.max_uses,
+				})
+				.await?;
+		}
+		C2S::RevokeInvite(revoke_invite) => {
+			let invite = match server.db.valid_chatroom_invite(&revoke_invite.code).await? {
+				Some(x) => x,
+				None => {
+					reject(socket, state, s2c::Error::InvalidInvite).await?;
+					return Ok(());
+				}
+			};
+
+			match server
+				.db
+				.chatroom_member(invite.chatroom, state.user_id)
+				.await?
+			{
+				Some(member) if member.role >= ChatroomRole::Moderator => {}
+				// dont reveal that the invite exists
+				_ => {
+					reject(socket, state, s2c::Error::InvalidInvite).await?;
+					return Ok(());
+				}
+			}
+
+			server.db.delete_chatroom_invite(&invite.code).await?;
+		}
+		C2S::RedeemInvite(redeem_invite) => {
+			let chatroom = match server
+				.db
+				.redeem_chatroom_invite(&redeem_invite.code, state.user_id)
+				.await?
+			{
+				Ok(x) => x,
+				Err(RedeemInviteError::Invalid) => {
+					reject(socket, state, s2c::Error::InvalidInvite).await?;
+					return Ok(());
+				}
+				Err(RedeemInviteError::Banned) => {
+					reject(socket, state, s2c::Error::BannedFromChatroom).await?;
+					return Ok(());
+				}
+			};
+
+			let info = server
+				.db
+				.chatroom_by_id(chatroom)
+				.await?
+				.context("redeemed chatroom doesnt exist")?;
+			let last_seq_id = server.db.fetch_last_message_seq_id(&chatroom).await?;
+
+			socket
+				.send_packet(s2c::InviteRedeemed(s2c::ChatroomInfo {
+					id: *chatroom.as_bytes(),
+					name: info.name,
+					archived: info.archived_at.is_some(),
+					last_seq_id: (last_seq_id >= 0).then_some(last_seq_id),
+				}))
+				.await?;
+		}
+		C2S::AddReaction(add_reaction) => {
+			let message_id = Uuid::from_bytes(add_reaction.message_id);
+
+			if !is_valid_reaction(&add_reaction.emoji) {
+				reject(socket, state, s2c::Error::InvalidReaction).await?;
+				return Ok(());
+			}
+
+			if let Some(error) = check_message_access(server, state, message_id).await? {
+				reject(socket, state, error).await?;
+				return Ok(());
+			}
+
+			server
+				.db
+				.add_reaction(message_id, state.user_id, &add_reaction.emoji)
+				.await?;
+		}
+		C2S::RemoveReaction(remove_reaction) => {
+			let message_id = Uuid::from_bytes(remove_reaction.message_id);
+
+			// removing is allowed even after losing access to the message
+			server
+				.db
+				.remove_reaction(message_id, state.user_id, &remove_reaction.emoji)
+				.await?;
+		}
+		C2S::MarkRead(mark_read) => {
+			let chatroom = Uuid::from_bytes(mark_read.chatroom);
+
+			if !server
+				.db
+				.is_chatroom_member(chatroom, state.user_id)
+				.await?
+			{
+				reject(socket, state, s2c::Error::NotChatroomMember).await?;
+				return Ok(());
+			}
+
+			server
+				.db
+				.mark_read(state.user_id, chatroom, mark_read.sequence_id)
+				.await?;
+		}
+		C2S::FetchUnreadCounts(_) => {
+			let unread_counts = server.db.unread_counts(state.user_id).await?;
+
+			socket
+				.send_packet(s2c::UnreadCounts {
+					chatrooms: unread_counts
+						.into_iter()
+						.map(|count| s2c::UnreadCount {
+							chatroom: *count.chatroom.as_bytes(),
+							last_read_seq_id: count.last_read_seq_id,
+							unread: count.unread.max(0) as u64,
+						})
+						.collect(),
+				})
+				.await?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Rejects the packet that is currently being handled, without closing the connection
+async fn reject(
+	socket: &mut Socket<'_>,
+	state: &ConnectionState,
+	error: s2c::Error,
+) -> Result<(), Error> {
+	socket
+		.send_packet(s2c::Rejected {
+			packet_index: state.packet_index,
+			error,
+		})
+		.await?;
+
+	Ok(())
+}
+
+async fn reject_message(
+	socket: &mut Socket<'_>,
+	state: &ConnectionState,
+	nonce: [u8; 16],
+	error: s2c::Error,
+) -> Result<(), Error> {
+	socket
+		.send_packet(s2c::MessageRejected {
+			nonce,
+			packet_index: state.packet_index,
+			error,
+		})
+		.await?;
+
+	Ok(())
+}
+
+/// Unsubscribes from everything in the chatroom, does nothing if it wasnt joined
+async fn unsubscribe_chatroom(
+	server: &mut ServerState,
+	state: &mut ConnectionState,
+	chatroom: Uuid,
+) -> Result<(), Error> {
+	let _ = state.update_subscriber.unsubscribe_chat(chatroom).await;
+	let _ = state.update_subscriber.unsubscribe_typing(chatroom).await;
+
+	if state.typing_in.remove(&chatroom) {
+		server
+			.db
+			.notify_typing(chatroom, state.user_id, false)
+			.await?;
+	}
+
+	Ok(())
+}
+
+/// The user is no longer a member of the chatroom, or it was deleted
+async fn remove_chatroom(
+	server: &mut ServerState,
+	state: &mut ConnectionState,
+	socket: &mut Socket<'_>,
+	chatroom: Uuid,
+) -> Result<(), Error> {
+	// no longer allowed to receive anything from it
+	unsubscribe_chatroom(server, state, chatroom).await?;
+	state
+		.update_subscriber
+		.unsubscribe_chat_presence(chatroom)
+		.await;
+
+	if state.capabilities.contains(Capabilities::CHATROOM_UPDATES) {
+		socket
+			.send_packet(s2c::ChatroomListChanged::Removed {
+				id: *chatroom.as_bytes(),
+			})
+			.await?;
+	}
+
+	Ok(())
+}
+
+async fn subscribe_members_presence(
+	server: &mut ServerState,
+	state: &mut ConnectionState,
+	chatroom: Uuid,
+) -> Result<(), Error> {
+	for member in server.db.chatroom_members(chatroom).await? {
+		if member.user_id != state.user_id {
+			state
+				.update_subscriber
+				.subscribe_presence(chatroom, member.user_id)
+				.await;
+		}
 	}
 
 	Ok(())
 }
+
+/// Checks that the user is the owner of the chatroom
+async fn check_chatroom_owner(
+	server: &mut ServerState,
+	state: &ConnectionState,
+	chatroom: Uuid,
+) -> Result<Option<s2c::Error>, Error> {
+	match server.db.chatroom_member(chatroom, state.user_id).await? {
+		Some(member) if member.role == ChatroomRole::Owner => Ok(None),
+		Some(_) => Ok(Some(s2c::Error::NotChatroomOwner)),
+		None => Ok(Some(s2c::Error::NotChatroomMember)),
+	}
+}
+
+/// Checks that the user can take moderation actions against `target` in the chatroom,
+/// which requires a higher role than the target. The target doesnt have to be a member
+async fn check_moderator(
+	server: &mut ServerState,
+	state: &ConnectionState,
+	chatroom: Uuid,
+	target: Uuid,
+) -> Result<Option<s2c::Error>, Error> {
+	let member = match server.db.chatroom_member(chatroom, state.user_id).await? {
+		Some(x) => x,
+		None => return Ok(Some(s2c::Error::NotChatroomMember)),
+	};
+
+	if member.role < ChatroomRole::Moderator || target == state.user_id {
+		return Ok(Some(s2c::Error::NotPermitted));
+	}
+
+	match server.db.chatroom_member(chatroom, target).await? {
+		Some(target) if target.role >= member.role => Ok(Some(s2c::Error::NotPermitted)),
+		_ => Ok(None),
+	}
+}
+
+/// Returns the trimmed name, or `None` if it isnt valid
+fn validate_chatroom_name(name: &str) -> Option<&str> {
+	let name = name.trim();
+
+	match name.chars().count() {
+		1..=MAX_CHATROOM_NAME_LEN => Some(name),
+		_ => None,
+	}
+}
+
+/// Checks that the reaction is made of emoji, such as a single one or a sequence like a flag or skin tone
+fn is_valid_reaction(emoji: &str) -> bool {
+	if emoji.is_empty() || emoji.len() > MAX_EMOJI_LEN {
+		return false;
+	}
+
+	// digits, '#' and '*' are emoji too, but only as keycaps
+	emoji.chars().all(|c| c.is_emoji_char_or_emoji_component())
+		&& emoji
+			.chars()
+			.any(|c| !c.is_ascii() && (c.is_emoji_char() || c == KEYCAP))
+}
+
+async fn is_chatroom_archived(server: &mut ServerState, chatroom: Uuid) -> Result<bool, Error> {
+	Ok(server
+		.db
+		.chatroom_by_id(chatroom)
+		.await?
+		.is_some_and(|chatroom| chatroom.archived_at.is_some()))
+}
+
+/// Checks that the message exists, wasnt deleted and the user is an unmuted member of its chatroom
+async fn check_message_access(
+	server: &mut ServerState,
+	state: &ConnectionState,
+	message_id: Uuid,
+) -> Result<Option<s2c::Error>, Error> {
+	Ok(message_with_access(server, state, message_id).await?.err())
+}
+
+/// Same as [`check_message_access`], but also checks that the user is the author
+async fn check_message_author(
+	server: &mut ServerState,
+	state: &ConnectionState,
+	message_id: Uuid,
+) -> Result<Option<s2c::Error>, Error> {
+	match message_with_access(server, state, message_id).await? {
+		Ok(msg) if msg.user_id != Some(state.user_id) => Ok(Some(s2c::Error::NotMessageAuthor)),
+		Ok(_) => Ok(None),
+		Err(e) => Ok(Some(e)),
+	}
+}
+
+async fn message_with_access(
+	server: &mut ServerState,
+	state: &ConnectionState,
+	message_id: Uuid,
+) -> Result<Result<Message, s2c::Error>, Error> {
+	let msg = match server.db.message_by_id(message_id).await? {
+		Some(msg) if msg.deleted_at.is_none() => msg,
+		_ => return Ok(Err(s2c::Error::MessageNotFound)),
+	};
+
+	match server
+		.db
+		.chatroom_member(msg.chatroom, state.user_id)
+		.await?
+	{
+		Some(member) if member.is_muted() => return Ok(Err(s2c::Error::Muted)),
+		Some(_) => {}
+		None => return Ok(Err(s2c::Error::NotChatroomMember)),
+	}
+
+	if is_chatroom_archived(server, msg.chatroom).await? {
+		return Ok(Err(s2c::Error::ChatroomArchived));
+	}
+
+	Ok(Ok(msg))
+}
+
+async fn chat_message(server: &mut ServerState, msg: &Message) -> Result<s2c::ChatMessage, Error> {
+	let (user_id, username) = match msg.user_id {
+		Some(user_id) => {
+			let username = server
+				.usernames
+				.get(user_id)
+				.await?
+				.with_context(|| format!("author of message {} doesnt exist", msg.id))?;
+
+			(user_id, username.to_string())
+		}
+		// the author deleted their account
+		None => (Uuid::nil(), String::new()),
+	};
+
+	Ok(s2c::ChatMessage {
+		id: *msg.id.as_bytes(),
+		chatroom: *msg.chatroom.as_bytes(),
+		sequence_id: msg.sequence_id,
+		user_id: *user_id.as_bytes(),
+		username,
+		message: msg.message.clone(),
+		sent_at: msg.sent_at.timestamp_millis(),
+		edited_at: msg.edited_at.map(|t| t.timestamp_millis()),
+		deleted_at: msg.deleted_at.map(|t| t.timestamp_millis()),
+		reactions: Vec::new(),
+		reply_to: msg.reply_to.map(|id| *id.as_bytes()),
+		thread_root: msg.thread_root.map(|id| *id.as_bytes()),
+	})
+}
+
+/// Converts the messages with their reaction counts as seen by the user
+async fn chat_messages_with_reactions(
+	server: &mut ServerState,
+	state: &ConnectionState,
+	messages: &[Message],
+) -> Result<Vec<s2c::ChatMessage>, Error> {
+	let message_ids: Vec<Uuid> = messages.iter().map(|msg| msg.id).collect();
+	let mut reactions: HashMap<Uuid, Vec<s2c::ReactionCount>> = HashMap::new();
+	for reaction in server
+		.db
+		.reaction_counts(&message_ids, state.user_id)
+		.await?
+	{
+		reactions
+			.entry(reaction.message_id)
+			.or_default()
+			.push(s2c::ReactionCount {
+				emoji: reaction.emoji,
+				count: reaction.count as u32,
+				reacted: reaction.reacted,
+			});
+	}
+
+	let mut chat_messages = Vec::with_capacity(messages.len());
+	for msg in messages {
+		let mut chat_message = chat_message(server, msg).await?;
+		chat_message.reactions = reactions.remove(&msg.id).unwrap_or_default();
+
+		chat_messages.push(chat_message);
+	}
+
+	Ok(chat_messages)
+}
+
+fn role_to_s2c(role: ChatroomRole) -> s2c::ChatroomRole {
+	match role {
+		ChatroomRole::Member => s2c::ChatroomRole::Member,
+		ChatroomRole::Moderator => s2c::ChatroomRole::Moderator,
+		ChatroomRole::Owner => s2c::ChatroomRole::Owner,
+	}
+}
+
+fn role_from_s2c(role: s2c::ChatroomRole) -> ChatroomRole {
+	match role {
+		s2c::ChatroomRole::Member => ChatroomRole::Member,
+		s2c::ChatroomRole::Moderator => ChatroomRole::Moderator,
+		s2c::ChatroomRole::Owner => ChatroomRole::Owner,
+	}
+}
+
+fn presence_to_s2c(presence: Presence) -> s2c::Presence {
+	match presence {
+		Presence::Online => s2c::Presence::Online,
+		Presence::Away => s2c::Presence::Away,
+		Presence::Offline => s2c::Presence::Offline,
+	}
+}
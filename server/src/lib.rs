@@ -1,16 +1,24 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use anyhow::Result;
-use axum::{Router, routing::any};
+use axum::{
+	Router,
+	routing::{any, get},
+};
 use clap::Parser;
 use config::Config;
 use database::Database;
 use email::Email;
-use endpoints::{auth::auth_routes, main::main_endpoint};
+use endpoints::{
+	auth::auth_routes,
+	invite::display_invite,
+	main::{main_endpoint, negotiated_endpoint},
+};
 use logging::init_logging;
 use sqlx::PgPool;
 use tracing::info;
 use update_listener::UpdateListener;
+use usernames::UsernameCache;
 
 pub mod cmd_args;
 pub mod config;
@@ -21,13 +29,17 @@ pub mod endpoints;
 pub mod logging;
 pub mod pages;
 pub mod populate;
+pub mod rate_limit;
 pub mod socket;
+pub mod totp;
 pub mod update_listener;
+pub mod usernames;
 
 #[derive(Clone)]
 pub struct ServerState {
 	pub db: Database<PgPool>,
 	pub updates: UpdateListener,
+	pub usernames: UsernameCache,
 	pub email: Email,
 	pub config: Arc<Config>,
 }
@@ -49,6 +61,7 @@ pub async fn main() -> Result<()> {
 
 	let state = ServerState {
 		updates: UpdateListener::init(&db).await?,
+		usernames: UsernameCache::new(&db),
 		db,
 		email: Email::init(&config)?,
 		config,
@@ -59,10 +72,16 @@ pub async fn main() -> Result<()> {
 	let app = Router::new()
 		.nest("/auth", auth_routes())
 		.route("/v{version}", any(main_endpoint))
+		.route("/connect", any(negotiated_endpoint))
+		.route("/invite/{code}", get(display_invite))
 		.with_state(state);
 
 	info!("TCP listener bound on {}", listener.local_addr()?);
-	axum::serve(listener, app).await?;
+	axum::serve(
+		listener,
+		app.into_make_service_with_connect_info::<SocketAddr>(),
+	)
+	.await?;
 
 	Ok(())
 }
@@ -3,6 +3,7 @@ use std::sync::OnceLock;
 
 pub mod email;
 pub mod email_verification;
+pub mod invite;
 
 /// used by the templates themselves
 pub mod palette {
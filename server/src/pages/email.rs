@@ -1,5 +1,13 @@
+mod account_deletion;
 mod account_reminder;
+mod email_change_code;
+mod email_changed;
+mod password_reset;
 mod verification_code;
 
+pub use account_deletion::account_deletion;
 pub use account_reminder::account_reminder;
+pub use email_change_code::email_change_code;
+pub use email_changed::email_changed;
+pub use password_reset::password_reset;
 pub use verification_code::verification_code;
@@ -0,0 +1,11 @@
+use askama::Template;
+
+pub fn account_deletion(deletion_date: &str) -> String {
+	#[derive(Template)]
+	#[template(path = "email/account_deletion.html")]
+	struct AccountDeletion<'a> {
+		deletion_date: &'a str,
+	}
+
+	AccountDeletion { deletion_date }.render().unwrap()
+}
@@ -0,0 +1,11 @@
+use askama::Template;
+
+pub fn email_change_code(verification_link: &str) -> String {
+	#[derive(Template)]
+	#[template(path = "email/email_change_code.html")]
+	struct EmailChangeCode<'a> {
+		verification_link: &'a str,
+	}
+
+	EmailChangeCode { verification_link }.render().unwrap()
+}
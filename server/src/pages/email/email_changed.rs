@@ -0,0 +1,11 @@
+use askama::Template;
+
+pub fn email_changed(new_email: &str) -> String {
+	#[derive(Template)]
+	#[template(path = "email/email_changed.html")]
+	struct EmailChanged<'a> {
+		new_email: &'a str,
+	}
+
+	EmailChanged { new_email }.render().unwrap()
+}
@@ -0,0 +1,11 @@
+use askama::Template;
+
+pub fn password_reset(reset_token: &str) -> String {
+	#[derive(Template)]
+	#[template(path = "email/password_reset.html")]
+	struct PasswordReset<'a> {
+		reset_token: &'a str,
+	}
+
+	PasswordReset { reset_token }.render().unwrap()
+}
@@ -0,0 +1,28 @@
+use askama::Template;
+use std::sync::OnceLock;
+
+pub fn invite_page(chatroom_name: &str, code: &str) -> String {
+	#[derive(Template)]
+	#[template(path = "invite.html")]
+	struct InvitePage<'a> {
+		chatroom_name: &'a str,
+		code: &'a str,
+	}
+
+	InvitePage {
+		chatroom_name,
+		code,
+	}
+	.render()
+	.unwrap()
+}
+
+pub fn invite_not_found_page() -> &'static str {
+	#[derive(Template)]
+	#[template(path = "invite_invalid.html")]
+	struct InviteNotFoundPage;
+
+	static PAGE: OnceLock<String> = OnceLock::new();
+
+	PAGE.get_or_init(|| InviteNotFoundPage.render().unwrap())
+}
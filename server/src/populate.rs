@@ -1,4 +1,7 @@
-use crate::database::Database;
+use crate::{
+	database::{Database, chatroom_members::ChatroomRole},
+	endpoints::auth::hash_token,
+};
 use sqlx::{PgPool, query};
 use uuid::Uuid;
 
@@ -8,8 +11,8 @@ pub const USER_A_TOKEN: Uuid = Uuid::from_u128(3);
 pub const USER_B_TOKEN: Uuid = Uuid::from_u128(4);
 pub const CHAT_ID: Uuid = Uuid::from_u128(5);
 
-pub async fn populate(db: &Database<PgPool>) -> anyhow::Result<()> {
-	let db = &db.inner;
+pub async fn populate(database: &Database<PgPool>) -> anyhow::Result<()> {
+	let db = &database.inner;
 
 	query!(
 		r#"INSERT INTO users (id, username, email, password) VALUES ($1, 'Alex', 'alex@email.com', 'test') ON CONFLICT (id) DO NOTHING"#,
@@ -21,26 +24,35 @@ pub async fn populate(db: &Database<PgPool>) -> anyhow::Result<()> {
 	).execute(db).await?;
 
 	query!(
-		r#"INSERT INTO active_sessions (token, user_id, expires_at) VALUES ($1, $2, NOW() + ('10 years'::interval)) ON CONFLICT (token) DO NOTHING"#,
-		USER_A_TOKEN,
+		r#"INSERT INTO active_sessions (token_hash, user_id, expires_at) VALUES ($1, $2, NOW() + ('10 years'::interval)) ON CONFLICT (token_hash) DO NOTHING"#,
+		hash_token(USER_A_TOKEN),
 		USER_A_ID,
 	)
 	.execute(db)
 	.await?;
 	query!(
-		r#"INSERT INTO active_sessions (token, user_id, expires_at) VALUES ($1, $2, NOW() + ('10 years'::interval)) ON CONFLICT (token) DO NOTHING"#,
-		USER_B_TOKEN,
+		r#"INSERT INTO active_sessions (token_hash, user_id, expires_at) VALUES ($1, $2, NOW() + ('10 years'::interval)) ON CONFLICT (token_hash) DO NOTHING"#,
+		hash_token(USER_B_TOKEN),
 		USER_B_ID,
 	)
 	.execute(db)
 	.await?;
 
 	query!(
-		r#"INSERT INTO chatrooms (id, name) VALUES ($1, 'test room') ON CONFLICT (id) DO NOTHING"#,
+		r#"INSERT INTO chatrooms (id, name, created_by) VALUES ($1, 'test room', $2) ON CONFLICT (id) DO NOTHING"#,
 		CHAT_ID,
+		USER_A_ID,
 	)
 	.execute(db)
 	.await?;
 
+	let mut database = database.clone();
+	for user_id in [USER_A_ID, USER_B_ID] {
+		database.add_chatroom_member(CHAT_ID, user_id).await?;
+	}
+	database
+		.set_chatroom_member_role(CHAT_ID, USER_A_ID, ChatroomRole::Owner)
+		.await?;
+
 	Ok(())
 }
@@ -0,0 +1,91 @@
+use crate::{ServerState, config::RateLimit};
+use std::{net::IpAddr, time::Duration};
+use uuid::Uuid;
+
+/// What is being rate limited, each has its own buckets
+#[derive(Clone, Copy, Debug)]
+pub enum Action {
+	Login,
+	/// Anything that sends an email from the auth endpoints
+	Email,
+	SendMessage,
+}
+
+impl Action {
+	fn name(self) -> &'static str {
+		match self {
+			Action::Login => "login",
+			Action::Email => "email",
+			Action::SendMessage => "send_message",
+		}
+	}
+}
+
+/// Who is doing the action, there is a bucket for each
+#[derive(Clone, Copy, Debug)]
+pub enum Key<'a> {
+	Ip(IpAddr),
+	Email(&'a str),
+	User(Uuid),
+}
+
+impl Key<'_> {
+	/// The key of the bucket in the database
+	pub fn to_bucket_key(self, action: Action) -> String {
+		match self {
+			Key::Ip(ip) => format!("{}:ip:{ip}", action.name()),
+			// emails are case insensitive
+			Key::Email(email) => format!("{}:email:{}", action.name(), email.to_lowercase()),
+			Key::User(user_id) => format!("{}:user:{user_id}", action.name()),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub struct RateLimited {
+	pub retry_after: Duration,
+}
+
+impl RateLimited {
+	/// Rounded up, so that retrying after it always succeeds
+	pub fn retry_after_secs(&self) -> u32 {
+		self.retry_after.as_secs_f64().ceil() as u32
+	}
+}
+
+/// Takes a token from the buckets of `keys` for the action, in order.
+/// Stops at the first empty one and returns how long until it has a token again,
+/// so that a client limited by its ip can't keep draining the bucket of someone else's email
+pub async fn rate_limit(
+	server: &mut ServerState,
+	action: Action,
+	keys: &[Key<'_>],
+) -> sqlx::Result<Result<(), RateLimited>> {
+	let limit = match action {
+		Action::Login => server.config.rate_limits.login,
+		Action::Email => server.config.rate_limits.email,
+		Action::SendMessage => server.config.rate_limits.send_message,
+	};
+
+	for key in keys {
+		let wait = server
+			.db
+			.take_rate_limit_token(
+				&key.to_bucket_key(action),
+				limit.burst,
+				refill_interval(limit),
+			)
+			.await?;
+
+		if let Some(retry_after) = wait {
+			return Ok(Err(RateLimited { retry_after }));
+		}
+	}
+
+	Ok(Ok(()))
+}
+
+/// How long it takes for a bucket to get a new token
+pub fn refill_interval(limit: RateLimit) -> Duration {
+	Duration::from_secs(60) / limit.per_minute.max(1)
+}
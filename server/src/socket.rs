@@ -1,3 +1,4 @@
+use axum::body::Bytes;
 use axum::extract::ws::{Message as WSMessage, WebSocket};
 use protocol::{C2S, IntoMessage, Message, S2C};
 use std::{future::pending, time::Duration};
@@ -14,6 +15,8 @@ pub struct Socket<'a> {
 	socket: &'a mut WebSocket,
 	ping_interval: Interval,
 	last_ping_sent: Option<Instant>,
+	// normal state packets are encoded in this version
+	protocol_version: u32,
 }
 
 #[derive(Error, Debug)]
@@ -38,9 +41,27 @@ impl<'a> Socket<'a> {
 			socket,
 			ping_interval,
 			last_ping_sent: None,
+			protocol_version: protocol::VERSION,
 		}
 	}
-	pub async fn send_packet(&mut self, msg: impl IntoMessage<S2C>) -> Result<(), axum::Error> {
+	pub fn set_protocol_version(&mut self, version: u32) {
+		self.protocol_version = version;
+	}
+	/// Sends a normal state packet, encoded in the protocol version of the connection
+	pub async fn send_packet(&mut self, packet: impl Into<S2C>) -> Result<(), axum::Error> {
+		let bytes = match packet.into().write_versioned(self.protocol_version) {
+			Some(x) => x,
+			// clients of that version never ask for it
+			None => return Ok(()),
+		};
+
+		self.socket.send(WSMessage::Binary(bytes.into())).await
+	}
+	/// Sends a state specific packet, which is the same in all protocol versions
+	pub async fn send_state_packet(
+		&mut self,
+		msg: impl IntoMessage<S2C>,
+	) -> Result<(), axum::Error> {
 		let bytes = msg.into_message().write();
 
 		self.socket.send(WSMessage::Binary(bytes.into())).await
@@ -48,7 +69,20 @@ impl<'a> Socket<'a> {
 	pub async fn close(&mut self) -> Result<(), axum::Error> {
 		self.socket.send(WSMessage::Close(None)).await
 	}
+	/// Receives a state specific packet, which is the same in all protocol versions
 	pub async fn recv<P: Message<C2S>>(&mut self) -> Result<P, RecvError> {
+		let packet_bytes = self.recv_frame().await?;
+
+		P::read(&packet_bytes).map_err(|_| RecvError::InvalidPacket)
+	}
+	/// Receives a normal state packet, encoded in the protocol version of the connection
+	pub async fn recv_packet(&mut self) -> Result<C2S, RecvError> {
+		let packet_bytes = self.recv_frame().await?;
+
+		C2S::read_versioned(&packet_bytes, self.protocol_version)
+			.map_err(|_| RecvError::InvalidPacket)
+	}
+	async fn recv_frame(&mut self) -> Result<Bytes, RecvError> {
 		loop {
 			select! {
 				_ = self.ping_interval.tick() => {
@@ -83,9 +117,7 @@ impl<'a> Socket<'a> {
 						_ => continue,
 					};
 
-					let packet = P::read(&packet_bytes).map_err(|_| RecvError::InvalidPacket)?;
-
-					return Ok(packet);
+					return Ok(packet_bytes);
 				},
 			}
 		}
@@ -0,0 +1,152 @@
+//! TOTP (RFC 6238) with the parameters that all authenticator apps support:
+//! HMAC-SHA1, 6 digits and 30 second steps
+
+use chacha20poly1305::{
+	Key, KeyInit, XChaCha20Poly1305, XNonce,
+	aead::{Aead, Payload},
+};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::{Rng, distr::Alphanumeric};
+use sha1::Sha1;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+const SECRET_LEN: usize = 20;
+const STEP_SECS: u64 = 30;
+const DIGITS: u32 = 6;
+/// How many steps a code can be off by, to allow for clock drift
+const ALLOWED_DRIFT: i64 = 1;
+const ISSUER: &str = "Salix";
+const NONCE_LEN: usize = 24;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LEN: usize = 10;
+
+pub fn generate_secret() -> Vec<u8> {
+	let mut secret = vec![0; SECRET_LEN];
+	rand::rng().fill(&mut secret[..]);
+
+	secret
+}
+
+/// Encrypts the secret for storing it in the database, so that the database alone
+/// isnt enough to generate codes. The user id is authenticated along with it,
+/// so that a secret cant be moved to another user
+pub fn encrypt_secret(key: &[u8; 32], user_id: Uuid, secret: &[u8]) -> Vec<u8> {
+	let mut nonce = [0; NONCE_LEN];
+	rand::rng().fill(&mut nonce);
+
+	let ciphertext = XChaCha20Poly1305::new(Key::from_slice(key))
+		.encrypt(
+			XNonce::from_slice(&nonce),
+			Payload {
+				msg: secret,
+				aad: user_id.as_bytes(),
+			},
+		)
+		.expect("encrypting into a vec doesnt fail");
+
+	[&nonce[..], &ciphertext].concat()
+}
+
+/// `None` if it wasnt encrypted with this key for this user
+pub fn decrypt_secret(key: &[u8; 32], user_id: Uuid, encrypted: &[u8]) -> Option<Vec<u8>> {
+	if encrypted.len() < NONCE_LEN {
+		return None;
+	}
+	let (nonce, ciphertext) = encrypted.split_at(NONCE_LEN);
+
+	XChaCha20Poly1305::new(Key::from_slice(key))
+		.decrypt(
+			XNonce::from_slice(nonce),
+			Payload {
+				msg: ciphertext,
+				aad: user_id.as_bytes(),
+			},
+		)
+		.ok()
+}
+
+/// The secret as entered into an authenticator app manually
+pub fn encode_secret(secret: &[u8]) -> String {
+	BASE32_NOPAD.encode(secret)
+}
+
+/// `otpauth://` URI to be shown as a QR code
+pub fn provisioning_uri(secret: &[u8], account: &str) -> String {
+	format!(
+		"otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={STEP_SECS}",
+		issuer = urlencoding::encode(ISSUER),
+		account = urlencoding::encode(account),
+		secret = encode_secret(secret),
+	)
+}
+
+/// Returns the time step of the code if it is valid right now
+pub fn verify(secret: &[u8], code: &str) -> Option<i64> {
+	verify_at(secret, code, current_step())
+}
+
+/// Returns the time step of the code if it is valid at the time step `now`
+pub fn verify_at(secret: &[u8], code: &str, now: i64) -> Option<i64> {
+	if !is_totp_code(code) {
+		return None;
+	}
+	let code: u32 = code.parse().ok()?;
+
+	(now - ALLOWED_DRIFT..=now + ALLOWED_DRIFT).find(|&step| code_at(secret, step) == code)
+}
+
+/// Whether it looks like a TOTP code, as opposed to a recovery code
+pub fn is_totp_code(code: &str) -> bool {
+	code.len() == DIGITS as usize && code.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn current_step() -> i64 {
+	let now = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.expect("system time before unix epoch");
+
+	(now.as_secs() / STEP_SECS) as i64
+}
+
+/// The HOTP (RFC 4226) value with the time step as the counter
+pub fn code_at(secret: &[u8], step: i64) -> u32 {
+	let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(secret).expect("HMAC takes keys of any size");
+	mac.update(&step.to_be_bytes());
+	let hash = mac.finalize().into_bytes();
+
+	// dynamic truncation
+	let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+	let binary = u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+
+	binary % 10u32.pow(DIGITS)
+}
+
+/// Formatted as `xxxxx-xxxxx` for readability
+pub fn generate_recovery_codes() -> Vec<String> {
+	(0..RECOVERY_CODE_COUNT)
+		.map(|_| {
+			let code: String = rand::rng()
+				.sample_iter(Alphanumeric)
+				.take(RECOVERY_CODE_LEN)
+				.map(|c| char::from(c).to_ascii_lowercase())
+				.collect();
+
+			format!(
+				"{}-{}",
+				&code[..RECOVERY_CODE_LEN / 2],
+				&code[RECOVERY_CODE_LEN / 2..]
+			)
+		})
+		.collect()
+}
+
+/// Recovery codes are compared ignoring case and the separator
+pub fn normalize_recovery_code(code: &str) -> String {
+	code.chars()
+		.filter(|c| c.is_ascii_alphanumeric())
+		.map(|c| c.to_ascii_lowercase())
+		.collect()
+}
@@ -1,29 +1,66 @@
-use crate::database::{Database, message::Message};
+use crate::database::Database;
 use futures::StreamExt;
 use messages::ChatroomContext;
 use sqlx::PgPool;
 use std::{
-	collections::{BTreeMap, VecDeque},
+	collections::{BTreeMap, BTreeSet, VecDeque},
 	sync::Arc,
 };
-use tokio_pubsub::{PublisherHandle, Subscriber};
+use tokio::select;
+use tokio_pubsub::{PubSubMessage, PublisherHandle, Subscriber};
+use typing::TypingContext;
 use uuid::Uuid;
 
 mod messages;
+mod presence;
+mod typing;
+mod user_events;
+
+pub use messages::ChatEvent;
+pub use presence::{PRESENCE_LIFETIME, PRESENCE_REFRESH_INTERVAL, PresenceEvent};
+pub use typing::TypingEvent;
+pub use user_events::UserEvent;
+
+/// Max number of missed messages that will be delivered when resuming a chat
+pub const MAX_RESUMED_MESSAGES: i64 = 500;
+
+#[derive(Clone, Debug)]
+pub enum Update {
+	Chat(Arc<ChatEvent>),
+	Typing(Arc<TypingEvent>),
+	Presence(Arc<PresenceEvent>),
+	User(Arc<UserEvent>),
+}
 
 #[derive(Clone, Debug)]
 pub struct UpdateListener {
 	database: Database<PgPool>,
-	messages: PublisherHandle<Uuid, Message, ChatroomContext>,
+	messages: PublisherHandle<Uuid, ChatEvent, ChatroomContext>,
+	typing: PublisherHandle<Uuid, TypingEvent, TypingContext>,
+	presence: PublisherHandle<Uuid, PresenceEvent, ()>,
+	user_events: PublisherHandle<Uuid, UserEvent, ()>,
 }
 
 #[derive(Debug)]
 pub struct UpdateSubscriber {
 	database: Database<PgPool>,
 
-	messages: Subscriber<Uuid, Message, ChatroomContext>,
+	messages: Subscriber<Uuid, ChatEvent, ChatroomContext>,
 	messages_last_seq_ids: BTreeMap<Uuid, i64>,
-	messages_buffer: VecDeque<Arc<Message>>,
+	messages_last_change_ids: BTreeMap<Uuid, i64>,
+	messages_buffer: VecDeque<Arc<ChatEvent>>,
+
+	typing: Subscriber<Uuid, TypingEvent, TypingContext>,
+	typing_chats: BTreeSet<Uuid>,
+	typing_buffer: VecDeque<Arc<TypingEvent>>,
+
+	presence: Subscriber<Uuid, PresenceEvent, ()>,
+	// members whose presence is subscribed through each chatroom
+	presence_chats: BTreeMap<Uuid, BTreeSet<Uuid>>,
+	// number of chatrooms in `presence_chats` that each subscribed user is in
+	presence_refs: BTreeMap<Uuid, u32>,
+
+	user_events: Subscriber<Uuid, UserEvent, ()>,
 }
 
 impl UpdateListener {
@@ -31,6 +68,9 @@ impl UpdateListener {
 		Ok(Self {
 			database: db.clone(),
 			messages: messages::start(db).await?,
+			typing: typing::start(db).await?,
+			presence: presence::start(db).await?,
+			user_events: user_events::start(db).await?,
 		})
 	}
 	pub async fn subscribe(&self) -> UpdateSubscriber {
@@ -39,66 +79,213 @@ impl UpdateListener {
 
 			messages: self.messages.subscribe().await.unwrap(),
 			messages_last_seq_ids: BTreeMap::new(),
+			messages_last_change_ids: BTreeMap::new(),
 			messages_buffer: VecDeque::new(),
+
+			typing: self.typing.subscribe().await.unwrap(),
+			typing_chats: BTreeSet::new(),
+			typing_buffer: VecDeque::new(),
+
+			presence: self.presence.subscribe().await.unwrap(),
+			presence_chats: BTreeMap::new(),
+			presence_refs: BTreeMap::new(),
+
+			user_events: self.user_events.subscribe().await.unwrap(),
 		}
 	}
 }
 
 impl UpdateSubscriber {
-	pub async fn recv_chat_messages(&mut self) -> sqlx::Result<Arc<Message>> {
-		if let Some(msg) = self.messages_buffer.pop_front() {
-			return Ok(msg);
-		}
+	/// Receives the next new message, edit, deletion, reaction or typing change in any of the subscribed chats,
+	/// presence change of any of the subscribed users, or event of the subscribed user
+	pub async fn recv(&mut self) -> sqlx::Result<Update> {
+		loop {
+			if let Some(event) = self.messages_buffer.pop_front() {
+				return Ok(Update::Chat(event));
+			}
+			if let Some(event) = self.typing_buffer.pop_front() {
+				return Ok(Update::Typing(event));
+			}
 
-		let (chat_id, msg) = self.messages.recv().await.unwrap();
+			select! {
+				biased;
 
-		let msg = match msg {
-			tokio_pubsub::PubSubMessage::Ok(x) => x,
-			tokio_pubsub::PubSubMessage::Lagged(n) => {
-				assert!(n != 0);
+				msg = self.messages.recv() => {
+					let (chat_id, msg) = msg.unwrap();
 
-				let last_seq_id = self.messages_last_seq_ids.get_mut(&chat_id).unwrap();
+					if let Some(event) = self.handle_chat_event(chat_id, msg).await? {
+						return Ok(Update::Chat(event));
+					}
+				}
+				msg = self.typing.recv() => {
+					let (chat_id, msg) = msg.unwrap();
 
-				let fetch_since = *last_seq_id;
-				let fetch_to = fetch_since + n as i64;
+					match msg {
+						// typing events that were already queued before unsubscribing from the chat
+						PubSubMessage::Ok(_) if !self.typing_chats.contains(&chat_id) => {}
+						PubSubMessage::Ok(event) => return Ok(Update::Typing(event)),
+						// typing is short lived anyway, not worth recovering
+						PubSubMessage::Lagged(_) => {}
+					}
+				}
+				msg = self.presence.recv() => {
+					let (user_id, msg) = msg.unwrap();
 
-				{
-					let mut stream = self
-						.database
-						.messages_by_seq_id(&chat_id, fetch_since..fetch_to);
+					match msg {
+						// presence events that were already queued before unsubscribing from the user
+						_ if !self.presence_refs.contains_key(&user_id) => {}
+						PubSubMessage::Ok(event) => return Ok(Update::Presence(event)),
+						// only the current presence matters
+						PubSubMessage::Lagged(_) => {
+							let presence = self.database.user_presence(user_id).await?;
 
-					while let Some(msg) = stream.next().await {
-						let msg = msg?;
+							return Ok(Update::Presence(Arc::new(PresenceEvent {
+								user_id,
+								presence,
+							})));
+						}
+					}
+				}
+				msg = self.user_events.recv() => {
+					let (_, msg) = msg.unwrap();
 
-						*last_seq_id = msg.sequence_id;
-						self.messages_buffer.push_back(Arc::new(msg));
+					match msg {
+						PubSubMessage::Ok(event) => return Ok(Update::User(event)),
+						PubSubMessage::Lagged(_) => return Ok(Update::User(Arc::new(UserEvent::Resync))),
 					}
 				}
+			}
+		}
+	}
+	/// Returns `None` if the event should be skipped, or if the missed
+	/// messages were put into the buffer after lagging
+	async fn handle_chat_event(
+		&mut self,
+		chat_id: Uuid,
+		event: PubSubMessage<ChatEvent>,
+	) -> sqlx::Result<Option<Arc<ChatEvent>>> {
+		// events that were already queued before unsubscribing from the chat
+		let last_seq_id = match self.messages_last_seq_ids.get_mut(&chat_id) {
+			Some(x) => x,
+			None => return Ok(None),
+		};
+
+		let last_change_id = self.messages_last_change_ids.get_mut(&chat_id).unwrap();
+
+		let event = match event {
+			PubSubMessage::Ok(x) => x,
+			PubSubMessage::Lagged(n) => {
+				assert!(n != 0);
+
+				// edits, deletions and reactions also count towards `n`, so this might fetch
+				// more messages than were missed, which is harmless
+				let fetch_since = *last_seq_id + 1;
+				let fetch_to = *last_seq_id + n as i64;
+
+				let mut stream = self
+					.database
+					.messages_by_seq_id(&chat_id, fetch_since..=fetch_to);
+
+				while let Some(msg) = stream.next().await {
+					let msg = msg?;
+
+					*last_seq_id = msg.sequence_id;
+					self.messages_buffer
+						.push_back(Arc::new(ChatEvent::NewMessage(msg)));
+				}
+				drop(stream);
+
+				// the missed changes are redelivered as the whole changed messages.
+				// `last_change_id` stays, the changes that come next are still delivered live
+				let changed = self
+					.database
+					.messages_changed_after(&chat_id, *last_change_id, None)
+					.await?;
 
-				return Ok(self.messages_buffer.pop_front().unwrap());
+				self.messages_buffer.extend(
+					changed
+						.into_iter()
+						// newer messages werent missed, their changes will come live after them
+						.filter(|msg| msg.sequence_id <= *last_seq_id)
+						.map(|msg| Arc::new(ChatEvent::MessageChanged(msg))),
+				);
+
+				return Ok(None);
 			}
 		};
 
-		assert_eq!(chat_id, msg.chatroom);
+		assert_eq!(chat_id, event.chatroom());
+
+		if let Some(change_id) = event.change_id() {
+			*last_change_id = change_id.max(*last_change_id);
+		}
+
+		if let ChatEvent::NewMessage(msg) = &*event {
+			// already delivered from the database (after lagging or when resuming)
+			if msg.sequence_id <= *last_seq_id {
+				return Ok(None);
+			}
 
-		*self.messages_last_seq_ids.get_mut(&chat_id).unwrap() = msg.sequence_id;
+			*last_seq_id = msg.sequence_id;
+		}
 
-		Ok(msg)
+		Ok(Some(event))
 	}
+	/// Subscribes to new messages in a chat.
+	///
+	/// If `last_seen_seq_id` is given, all messages after it will be delivered first,
+	/// (up to [`MAX_RESUMED_MESSAGES`] latest ones) before continuing with new messages.
+	/// Same with `last_seen_change_id` and the messages that changed after it.
 	pub async fn subscribe_chat(
 		&mut self,
 		chat_id: Uuid,
-	) -> Result<(), tokio_pubsub::error::TopicAlreadyAdded> {
-		match self.messages.add_topic(chat_id).await {
-			Ok(ctx) => {
-				self.messages_last_seq_ids
-					.insert(chat_id, ctx.last_message_seq_id);
+		last_seen_seq_id: Option<i64>,
+		last_seen_change_id: Option<i64>,
+	) -> sqlx::Result<Result<(), tokio_pubsub::error::TopicAlreadyAdded>> {
+		let ctx = match self.messages.add_topic(chat_id).await {
+			Ok(ctx) => ctx,
+			Err(tokio_pubsub::error::AddTopicError::AlreadyAdded(e)) => return Ok(Err(e)),
+			Err(other) => panic!("{other}"),
+		};
 
-				Ok(())
+		// all messages after this one will be received live
+		let last_message_seq_id = ctx.last_message_seq_id;
+
+		if let Some(last_seen_seq_id) = last_seen_seq_id {
+			let fetch_since =
+				(last_seen_seq_id + 1).max(last_message_seq_id - MAX_RESUMED_MESSAGES + 1);
+
+			let mut stream = self
+				.database
+				.messages_by_seq_id(&chat_id, fetch_since..=last_message_seq_id);
+
+			while let Some(msg) = stream.next().await {
+				self.messages_buffer
+					.push_back(Arc::new(ChatEvent::NewMessage(msg?)));
 			}
-			Err(tokio_pubsub::error::AddTopicError::AlreadyAdded(e)) => Err(e),
-			Err(other) => panic!("{other}"),
 		}
+
+		if let Some(last_seen_change_id) = last_seen_change_id {
+			let changed = self
+				.database
+				.messages_changed_after(&chat_id, last_seen_change_id, Some(MAX_RESUMED_MESSAGES))
+				.await?;
+
+			self.messages_buffer.extend(
+				changed
+					.into_iter()
+					// messages after this one and their changes will be received live
+					.filter(|msg| msg.sequence_id <= last_message_seq_id)
+					.map(|msg| Arc::new(ChatEvent::MessageChanged(msg))),
+			);
+		}
+
+		self.messages_last_seq_ids
+			.insert(chat_id, last_message_seq_id);
+		self.messages_last_change_ids
+			.insert(chat_id, ctx.last_change_id);
+
+		Ok(Ok(()))
 	}
 	pub async fn unsubscribe_chat(
 		&mut self,
@@ -107,6 +294,9 @@ impl UpdateSubscriber {
 		match self.messages.remove_topic(chat_id).await {
 			Ok(()) => {
 				self.messages_last_seq_ids.remove(&chat_id);
+				self.messages_last_change_ids.remove(&chat_id);
+				self.messages_buffer
+					.retain(|event| event.chatroom() != chat_id);
 
 				Ok(())
 			}
@@ -114,7 +304,130 @@ impl UpdateSubscriber {
 			Err(other) => panic!("{other}"),
 		}
 	}
+	pub fn is_chat_subscribed(&self, chat_id: Uuid) -> bool {
+		self.messages_last_seq_ids.contains_key(&chat_id)
+	}
+	/// Chats with messages, typing or presence of members subscribed
+	pub fn subscribed_chats(&self) -> BTreeSet<Uuid> {
+		self.messages_last_seq_ids
+			.keys()
+			.chain(&self.typing_chats)
+			.chain(self.presence_chats.keys())
+			.copied()
+			.collect()
+	}
+	/// Subscribes to typing changes in a chat.
+	///
+	/// Users that are already typing will be delivered first as started typing.
+	pub async fn subscribe_typing(
+		&mut self,
+		chat_id: Uuid,
+	) -> Result<(), tokio_pubsub::error::TopicAlreadyAdded> {
+		let ctx = match self.typing.add_topic(chat_id).await {
+			Ok(ctx) => ctx,
+			Err(tokio_pubsub::error::AddTopicError::AlreadyAdded(e)) => return Err(e),
+			Err(other) => panic!("{other}"),
+		};
+
+		self.typing_chats.insert(chat_id);
+
+		for user_id in ctx.typing {
+			self.typing_buffer.push_back(Arc::new(TypingEvent {
+				chatroom: chat_id,
+				user_id,
+				typing: true,
+			}));
+		}
+
+		Ok(())
+	}
+	pub async fn unsubscribe_typing(
+		&mut self,
+		chat_id: Uuid,
+	) -> Result<(), tokio_pubsub::error::TopicNotSubscribed> {
+		match self.typing.remove_topic(chat_id).await {
+			Ok(()) => {
+				self.typing_chats.remove(&chat_id);
+				self.typing_buffer.retain(|event| event.chatroom != chat_id);
+
+				Ok(())
+			}
+			Err(tokio_pubsub::error::RemoveTopicError::NotSubscribed(e)) => Err(e),
+			Err(other) => panic!("{other}"),
+		}
+	}
+	/// Subscribes to presence changes of a member of a chat.
+	///
+	/// The user stays subscribed until they are unsubscribed through
+	/// all the chats they were subscribed through
+	pub async fn subscribe_presence(&mut self, chat_id: Uuid, user_id: Uuid) {
+		if !self
+			.presence_chats
+			.entry(chat_id)
+			.or_default()
+			.insert(user_id)
+		{
+			return;
+		}
+
+		let refs = self.presence_refs.entry(user_id).or_insert(0);
+		*refs += 1;
+
+		if *refs == 1
+			&& let Err(e) = self.presence.add_topic(user_id).await
+		{
+			panic!("{e}");
+		}
+	}
+	/// Unsubscribes from presence changes of a member that left a chat
+	pub async fn unsubscribe_presence(&mut self, chat_id: Uuid, user_id: Uuid) {
+		let members = match self.presence_chats.get_mut(&chat_id) {
+			Some(x) => x,
+			None => return,
+		};
+
+		if !members.remove(&user_id) {
+			return;
+		}
+		if members.is_empty() {
+			self.presence_chats.remove(&chat_id);
+		}
+
+		self.release_presence(user_id).await;
+	}
+	/// Unsubscribes from presence changes of all members of a chat
+	pub async fn unsubscribe_chat_presence(&mut self, chat_id: Uuid) {
+		for user_id in self.presence_chats.remove(&chat_id).unwrap_or_default() {
+			self.release_presence(user_id).await;
+		}
+	}
+	async fn release_presence(&mut self, user_id: Uuid) {
+		let refs = self.presence_refs.get_mut(&user_id).unwrap();
+		*refs -= 1;
+
+		if *refs == 0 {
+			self.presence_refs.remove(&user_id);
+
+			if let Err(e) = self.presence.remove_topic(user_id).await {
+				panic!("{e}");
+			}
+		}
+	}
+	/// Subscribes to events of a user, normally the connected one
+	pub async fn subscribe_user_events(
+		&mut self,
+		user_id: Uuid,
+	) -> Result<(), tokio_pubsub::error::TopicAlreadyAdded> {
+		match self.user_events.add_topic(user_id).await {
+			Ok(()) => Ok(()),
+			Err(tokio_pubsub::error::AddTopicError::AlreadyAdded(e)) => Err(e),
+			Err(other) => panic!("{other}"),
+		}
+	}
 	pub async fn destroy(self) {
 		self.messages.destroy().await;
+		self.typing.destroy().await;
+		self.presence.destroy().await;
+		self.user_events.destroy().await;
 	}
 }
@@ -19,24 +19,85 @@ pub struct MessagesListener {
 	chatrooms: HashMap<Uuid, ChatroomState>,
 }
 
-type MessagesPublisher = Publisher<Uuid, Message, ChatroomContext>;
+type MessagesPublisher = Publisher<Uuid, ChatEvent, ChatroomContext>;
+
+#[derive(Clone, Debug)]
+pub enum ChatEvent {
+	NewMessage(Message),
+	MessageEdited {
+		id: Uuid,
+		chatroom: Uuid,
+		sequence_id: i64,
+		change_id: i64,
+		message: String,
+		edited_at: DateTime<Local>,
+	},
+	MessageDeleted {
+		id: Uuid,
+		chatroom: Uuid,
+		sequence_id: i64,
+		change_id: i64,
+		deleted_at: DateTime<Local>,
+	},
+	ReactionUpdated {
+		message_id: Uuid,
+		chatroom: Uuid,
+		sequence_id: i64,
+		change_id: i64,
+		user_id: Uuid,
+		emoji: String,
+		/// `false` if the reaction was removed
+		added: bool,
+		/// number of reactions with this emoji after the change
+		count: i64,
+	},
+	/// The current state of a message whose changes might have been missed,
+	/// always has a `change_id`
+	MessageChanged(Message),
+}
+
+impl ChatEvent {
+	pub fn chatroom(&self) -> Uuid {
+		match self {
+			ChatEvent::NewMessage(msg) => msg.chatroom,
+			ChatEvent::MessageEdited { chatroom, .. } => *chatroom,
+			ChatEvent::MessageDeleted { chatroom, .. } => *chatroom,
+			ChatEvent::ReactionUpdated { chatroom, .. } => *chatroom,
+			ChatEvent::MessageChanged(msg) => msg.chatroom,
+		}
+	}
+	/// `None` for new messages
+	pub fn change_id(&self) -> Option<i64> {
+		match self {
+			ChatEvent::NewMessage(_) => None,
+			ChatEvent::MessageEdited { change_id, .. } => Some(*change_id),
+			ChatEvent::MessageDeleted { change_id, .. } => Some(*change_id),
+			ChatEvent::ReactionUpdated { change_id, .. } => Some(*change_id),
+			ChatEvent::MessageChanged(msg) => msg.change_id,
+		}
+	}
+}
 
 struct ChatroomState {
 	// will stop listening when it reaches 0
 	listeners_n: u32,
 	// the sequential ID of the last received message
 	last_received_seq_id: i64,
+	// the ID of the last received edit, deletion or reaction change
+	last_received_change_id: i64,
 }
 
 pub struct ChatroomContext {
 	/// guarantees that all messages AFTER this seq id will be
 	/// delivered as long as you keep listening
 	pub last_message_seq_id: i64,
+	/// same for edits, deletions and reaction changes
+	pub last_change_id: i64,
 }
 
 pub async fn start(
 	db: &Database<PgPool>,
-) -> sqlx::Result<PublisherHandle<Uuid, Message, ChatroomContext>> {
+) -> sqlx::Result<PublisherHandle<Uuid, ChatEvent, ChatroomContext>> {
 	let publisher = Publisher::new();
 	let handle = publisher.handle();
 
@@ -107,14 +168,48 @@ impl MessagesListener {
 
 		let chat_id: Uuid = uuid_from_channel_name(notification.channel());
 
+		// was already in flight when the last listener unsubscribed
+		let chatroom = match self.chatrooms.get_mut(&chat_id) {
+			Some(x) => x,
+			None => return Ok(()),
+		};
+
 		#[derive(Clone, Debug, Deserialize)]
-		struct NotificationPayload {
-			id: Uuid,
-			sequence_id: i64,
-			user_id: Uuid,
-			#[serde(default)]
-			message: Option<String>,
-			sent_at: DateTime<Local>,
+		#[serde(tag = "kind", rename_all = "snake_case")]
+		enum NotificationPayload {
+			New {
+				id: Uuid,
+				sequence_id: i64,
+				user_id: Uuid,
+				#[serde(default)]
+				message: Option<String>,
+				sent_at: DateTime<Local>,
+				reply_to: Option<Uuid>,
+				thread_root: Option<Uuid>,
+			},
+			Edit {
+				id: Uuid,
+				sequence_id: i64,
+				change_id: i64,
+				#[serde(default)]
+				message: Option<String>,
+				edited_at: DateTime<Local>,
+			},
+			Delete {
+				id: Uuid,
+				sequence_id: i64,
+				change_id: i64,
+				deleted_at: DateTime<Local>,
+			},
+			Reaction {
+				id: Uuid,
+				sequence_id: i64,
+				change_id: i64,
+				user_id: Uuid,
+				emoji: String,
+				added: bool,
+				count: i64,
+			},
 		}
 
 		let payload: NotificationPayload = match serde_json::from_str(notification.payload()) {
@@ -127,37 +222,116 @@ impl MessagesListener {
 			}
 		};
 
-		let new_message;
-		if let Some(message) = payload.message {
-			new_message = Message {
-				id: payload.id,
-				chatroom: chat_id,
-				sequence_id: payload.sequence_id,
-				user_id: payload.user_id,
+		let event = match payload {
+			NotificationPayload::New {
+				id,
+				sequence_id,
+				user_id,
 				message,
-				sent_at: payload.sent_at,
-			};
-		} else {
-			// full message couldnt fit in the notification payload, gotta fetch it manually
-			new_message = self.db.message_by_id(payload.id).await?.unwrap();
-		}
+				sent_at,
+				reply_to,
+				thread_root,
+			} => {
+				let new_message;
+				if let Some(message) = message {
+					new_message = Message {
+						id,
+						chatroom: chat_id,
+						sequence_id,
+						user_id: Some(user_id),
+						message,
+						sent_at,
+						edited_at: None,
+						deleted_at: None,
+						reply_to,
+						thread_root,
+						change_id: None,
+					};
+				} else {
+					// full message couldnt fit in the notification payload, gotta fetch it manually
+					new_message = match self.db.message_by_id(id).await? {
+						Some(x) => x,
+						// the chatroom was deleted in the meantime
+						None => return Ok(()),
+					};
+				}
 
-		self.chatrooms
-			.get_mut(&chat_id)
-			.unwrap()
-			.last_received_seq_id = payload.sequence_id;
+				ChatEvent::NewMessage(new_message)
+			}
+			NotificationPayload::Edit {
+				id,
+				sequence_id,
+				change_id,
+				message,
+				edited_at,
+			} => {
+				let message = match message {
+					Some(x) => x,
+					// same as with new messages
+					None => match self.db.message_by_id(id).await? {
+						Some(x) => x.message,
+						None => return Ok(()),
+					},
+				};
+
+				ChatEvent::MessageEdited {
+					id,
+					chatroom: chat_id,
+					sequence_id,
+					change_id,
+					message,
+					edited_at,
+				}
+			}
+			NotificationPayload::Delete {
+				id,
+				sequence_id,
+				change_id,
+				deleted_at,
+			} => ChatEvent::MessageDeleted {
+				id,
+				chatroom: chat_id,
+				sequence_id,
+				change_id,
+				deleted_at,
+			},
+			NotificationPayload::Reaction {
+				id,
+				sequence_id,
+				change_id,
+				user_id,
+				emoji,
+				added,
+				count,
+			} => ChatEvent::ReactionUpdated {
+				message_id: id,
+				chatroom: chat_id,
+				sequence_id,
+				change_id,
+				user_id,
+				emoji,
+				added,
+				count,
+			},
+		};
 
-		publisher.publish(&chat_id, new_message).unwrap();
+		match &event {
+			ChatEvent::NewMessage(msg) => chatroom.last_received_seq_id = msg.sequence_id,
+			other => chatroom.last_received_change_id = other.change_id().unwrap(),
+		}
+
+		publisher.publish(&chat_id, event).unwrap();
 
 		Ok(())
 	}
-	// gets called when the database connection is disrupted and there might have been missed new messages
+	// gets called when the database connection is disrupted and there might have been missed new messages.
+	// messages that were edited, deleted or reacted to in the meantime are redelivered whole
 	async fn on_db_conn_disruption(
 		&mut self,
 		publisher: &mut MessagesPublisher,
 	) -> sqlx::Result<()> {
 		for (chat_id, chatroom) in &mut self.chatrooms {
-			let fetch_since = chatroom.last_received_seq_id;
+			let fetch_since = chatroom.last_received_seq_id + 1;
 
 			let mut msg_stream = self.db.messages_by_seq_id(chat_id, fetch_since..);
 
@@ -165,7 +339,22 @@ impl MessagesListener {
 				let msg = msg?;
 
 				chatroom.last_received_seq_id = msg.sequence_id;
-				publisher.publish(chat_id, msg).unwrap();
+				publisher
+					.publish(chat_id, ChatEvent::NewMessage(msg))
+					.unwrap();
+			}
+			drop(msg_stream);
+
+			let changed = self
+				.db
+				.messages_changed_after(chat_id, chatroom.last_received_change_id, None)
+				.await?;
+
+			for msg in changed {
+				chatroom.last_received_change_id = msg.change_id.unwrap();
+				publisher
+					.publish(chat_id, ChatEvent::MessageChanged(msg))
+					.unwrap();
 			}
 		}
 
@@ -184,6 +373,7 @@ impl MessagesListener {
 
 		Ok(ChatroomContext {
 			last_message_seq_id: chatroom_data.last_received_seq_id,
+			last_change_id: chatroom_data.last_received_change_id,
 		})
 	}
 	async fn on_unsubscribe(&mut self, topic: &Uuid) -> Result<(), sqlx::Error> {
@@ -215,9 +405,11 @@ async fn start_chat_listen(
 	// now we are already listening, so we can fetch the current last message seq id
 	// and be sure that we are not gonna miss any since that one
 	let last_seq_id = db.fetch_last_message_seq_id(topic).await?;
+	let last_change_id = db.fetch_last_message_change_id(topic).await?;
 
 	Ok(ChatroomState {
 		listeners_n: 0,
 		last_received_seq_id: last_seq_id,
+		last_received_change_id: last_change_id,
 	})
 }
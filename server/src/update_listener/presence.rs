@@ -0,0 +1,211 @@
+use crate::database::{Database, presence::Presence};
+use ahash::{HashMap, HashMapExt};
+use anyhow::{Context, bail};
+use serde::Deserialize;
+use sqlx::{
+	PgPool,
+	postgres::{PgListener, PgNotification},
+};
+use std::{collections::hash_map::Entry, convert::Infallible, time::Duration};
+use tokio::{
+	select, spawn,
+	time::{MissedTickBehavior, interval},
+};
+use tokio_pubsub::{EventReactor, Publisher, PublisherHandle};
+use tracing::error;
+use uuid::Uuid;
+
+/// How often a connected socket has to refresh its presence
+pub const PRESENCE_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+/// How long the presence of a socket lasts without being refreshed.
+///
+/// Normally sockets remove their presence when closing, this is only
+/// for server instances that die without cleaning up.
+pub const PRESENCE_LIFETIME: Duration = Duration::from_secs(90);
+
+const CHANNEL_NAME: &str = "presence";
+
+pub struct PresenceListener {
+	db: Database<PgListener>,
+	users: HashMap<Uuid, UserState>,
+}
+
+type PresencePublisher = Publisher<Uuid, PresenceEvent, ()>;
+
+#[derive(Clone, Debug)]
+pub struct PresenceEvent {
+	pub user_id: Uuid,
+	pub presence: Presence,
+}
+
+struct UserState {
+	// will stop tracking when it reaches 0
+	listeners_n: u32,
+	// the last published presence
+	presence: Presence,
+}
+
+pub async fn start(
+	db: &Database<PgPool>,
+) -> sqlx::Result<PublisherHandle<Uuid, PresenceEvent, ()>> {
+	let publisher = Publisher::new();
+	let handle = publisher.handle();
+
+	let presence_listener = PresenceListener::new(db).await?;
+	spawn(async move {
+		if let Err(e) = presence_listener.run(publisher).await {
+			error!("{e:?}");
+		}
+	});
+
+	Ok(handle)
+}
+
+impl PresenceListener {
+	pub async fn new(db: &Database<PgPool>) -> sqlx::Result<Self> {
+		let mut listener = PgListener::connect_with(&db.inner).await?;
+
+		// there is only a single channel for all users
+		listener.listen(CHANNEL_NAME).await?;
+
+		Ok(Self {
+			db: Database::new(listener),
+			users: HashMap::new(),
+		})
+	}
+	// publisher has to be separate from Self, because drive() borrows self, and we need self again to finish it
+	pub async fn run(mut self, mut publisher: PresencePublisher) -> anyhow::Result<()> {
+		let mut expire_interval = interval(PRESENCE_REFRESH_INTERVAL);
+		expire_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+		loop {
+			select! {
+				driver = publisher.drive() => {
+					struct Reactor<'a>(&'a mut PresenceListener);
+
+					impl<'a> EventReactor<Uuid, (), Infallible> for Reactor<'a> {
+						type Error = sqlx::Error;
+
+						async fn on_subscribe(
+							&mut self,
+							topic: &Uuid,
+						) -> Result<Result<(), Infallible>, Self::Error> {
+							self.0.on_subscribe(topic).await.map(Ok)
+						}
+						async fn on_unsubscribe(&mut self, topic: &Uuid) -> Result<(), Self::Error> {
+							self.0.on_unsubscribe(topic);
+							Ok(())
+						}
+					}
+
+					driver.finish(Reactor(&mut self)).await?;
+				},
+				notification = self.db.try_recv() => {
+					self.handle_notification(&mut publisher, notification?).await.context("handle notification")?;
+				}
+				_ = expire_interval.tick() => {
+					// every server instance does this, but it is cheap enough.
+					// the deletions notify as usual
+					self.db
+						.delete_expired_presence_sockets()
+						.await
+						.context("delete expired presence")?;
+				}
+			}
+		}
+	}
+	async fn handle_notification(
+		&mut self,
+		publisher: &mut PresencePublisher,
+		notification: Option<PgNotification>,
+	) -> anyhow::Result<()> {
+		let notification = match notification {
+			Some(x) => x,
+			None => {
+				// disrupted connection, refetch presence of all tracked users and continue
+				self.on_db_conn_disruption(publisher)
+					.await
+					.context("handle conn disruption")?;
+
+				return Ok(());
+			}
+		};
+
+		#[derive(Clone, Debug, Deserialize)]
+		struct NotificationPayload {
+			user_id: Uuid,
+			presence: Presence,
+		}
+
+		let payload: NotificationPayload = match serde_json::from_str(notification.payload()) {
+			Ok(x) => x,
+			Err(e) => {
+				bail!(
+					"couldnt parse notification payload ({}): {e}",
+					notification.payload()
+				);
+			}
+		};
+
+		if let Some(user) = self.users.get_mut(&payload.user_id) {
+			update_presence(publisher, payload.user_id, user, payload.presence);
+		}
+
+		Ok(())
+	}
+	// gets called when the database connection is disrupted and there might have been missed changes
+	async fn on_db_conn_disruption(
+		&mut self,
+		publisher: &mut PresencePublisher,
+	) -> sqlx::Result<()> {
+		for (user_id, user) in &mut self.users {
+			let presence = self.db.user_presence(*user_id).await?;
+
+			update_presence(publisher, *user_id, user, presence);
+		}
+
+		Ok(())
+	}
+	async fn on_subscribe(&mut self, topic: &Uuid) -> sqlx::Result<()> {
+		let user = match self.users.entry(*topic) {
+			Entry::Occupied(occupied_entry) => occupied_entry.into_mut(),
+			Entry::Vacant(vacant_entry) => {
+				let presence = self.db.user_presence(*topic).await?;
+
+				vacant_entry.insert(UserState {
+					listeners_n: 0,
+					presence,
+				})
+			}
+		};
+		user.listeners_n += 1;
+
+		Ok(())
+	}
+	fn on_unsubscribe(&mut self, topic: &Uuid) {
+		let listeners_n = &mut self.users.get_mut(topic).unwrap().listeners_n;
+		*listeners_n -= 1;
+
+		if *listeners_n == 0 {
+			self.users.remove(topic);
+		}
+	}
+}
+
+// publishes only if the presence actually changed
+fn update_presence(
+	publisher: &mut PresencePublisher,
+	user_id: Uuid,
+	user: &mut UserState,
+	presence: Presence,
+) {
+	if user.presence == presence {
+		return;
+	}
+
+	user.presence = presence;
+
+	publisher
+		.publish(&user_id, PresenceEvent { user_id, presence })
+		.unwrap();
+}
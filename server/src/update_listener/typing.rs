@@ -0,0 +1,237 @@
+use crate::database::Database;
+use ahash::{HashMap, HashMapExt};
+use anyhow::{Context, bail};
+use serde::Deserialize;
+use sqlx::{
+	PgPool,
+	postgres::{PgListener, PgNotification},
+};
+use std::{collections::hash_map::Entry, convert::Infallible, future::pending, time::Duration};
+use tokio::{
+	select, spawn,
+	time::{Instant, sleep_until},
+};
+use tokio_pubsub::{EventReactor, Publisher, PublisherHandle};
+use tracing::error;
+use uuid::Uuid;
+
+/// How long a user is shown as typing after their last typing notification
+pub const TYPING_TIMEOUT: Duration = Duration::from_secs(6);
+
+pub struct TypingListener {
+	db: Database<PgListener>,
+	chatrooms: HashMap<Uuid, ChatroomState>,
+}
+
+type TypingPublisher = Publisher<Uuid, TypingEvent, TypingContext>;
+
+#[derive(Clone, Debug)]
+pub struct TypingEvent {
+	pub chatroom: Uuid,
+	pub user_id: Uuid,
+	/// `false` if the user stopped typing or their typing expired
+	pub typing: bool,
+}
+
+struct ChatroomState {
+	// will stop listening when it reaches 0
+	listeners_n: u32,
+	// users that are currently typing and when their typing expires
+	typing: HashMap<Uuid, Instant>,
+}
+
+pub struct TypingContext {
+	/// users that were already typing when subscribing
+	pub typing: Vec<Uuid>,
+}
+
+pub async fn start(
+	db: &Database<PgPool>,
+) -> sqlx::Result<PublisherHandle<Uuid, TypingEvent, TypingContext>> {
+	let publisher = Publisher::new();
+	let handle = publisher.handle();
+
+	let typing_listener = TypingListener::new(db).await?;
+	spawn(async move {
+		if let Err(e) = typing_listener.run(publisher).await {
+			error!("{e:?}");
+		}
+	});
+
+	Ok(handle)
+}
+
+impl TypingListener {
+	pub async fn new(db: &Database<PgPool>) -> sqlx::Result<Self> {
+		let listener = PgListener::connect_with(&db.inner).await?;
+
+		Ok(Self {
+			db: Database::new(listener),
+			chatrooms: HashMap::new(),
+		})
+	}
+	// publisher has to be separate from Self, because drive() borrows self, and we need self again to finish it
+	pub async fn run(mut self, mut publisher: TypingPublisher) -> anyhow::Result<()> {
+		loop {
+			let next_expiry = self
+				.chatrooms
+				.values()
+				.flat_map(|chatroom| chatroom.typing.values())
+				.min()
+				.copied();
+
+			select! {
+				driver = publisher.drive() => {
+					struct Reactor<'a>(&'a mut TypingListener);
+
+					impl<'a> EventReactor<Uuid, TypingContext, Infallible> for Reactor<'a> {
+						type Error = sqlx::Error;
+
+						async fn on_subscribe(
+							&mut self,
+							topic: &Uuid,
+						) -> Result<Result<TypingContext, Infallible>, Self::Error> {
+							self.0.on_subscribe(topic).await.map(Ok)
+						}
+						async fn on_unsubscribe(&mut self, topic: &Uuid) -> Result<(), Self::Error> {
+							self.0.on_unsubscribe(topic).await
+						}
+					}
+
+					driver.finish(Reactor(&mut self)).await?;
+				},
+				notification = self.db.try_recv() => {
+					self.handle_notification(&mut publisher, notification?).await.context("handle notification")?;
+				}
+				_ = async {
+					match next_expiry {
+						Some(t) => sleep_until(t).await,
+						None => pending().await,
+					}
+				} => {
+					self.expire_typing(&mut publisher);
+				}
+			}
+		}
+	}
+	async fn handle_notification(
+		&mut self,
+		publisher: &mut TypingPublisher,
+		notification: Option<PgNotification>,
+	) -> anyhow::Result<()> {
+		let notification = match notification {
+			Some(x) => x,
+			// disrupted connection, missed notifications are not important enough
+			// to recover, typing will just expire on its own
+			None => return Ok(()),
+		};
+
+		let chat_id: Uuid = uuid_from_channel_name(notification.channel());
+
+		#[derive(Clone, Debug, Deserialize)]
+		struct NotificationPayload {
+			user_id: Uuid,
+			typing: bool,
+		}
+
+		let payload: NotificationPayload = match serde_json::from_str(notification.payload()) {
+			Ok(x) => x,
+			Err(e) => {
+				bail!(
+					"couldnt parse notification payload ({}): {e}",
+					notification.payload()
+				);
+			}
+		};
+
+		let typing = match self.chatrooms.get_mut(&chat_id) {
+			Some(chatroom) => &mut chatroom.typing,
+			// was already in flight when the last listener unsubscribed
+			None => return Ok(()),
+		};
+
+		// only changes get published, refreshing the typing just extends the expiry
+		let changed = if payload.typing {
+			typing
+				.insert(payload.user_id, Instant::now() + TYPING_TIMEOUT)
+				.is_none()
+		} else {
+			typing.remove(&payload.user_id).is_some()
+		};
+
+		if changed {
+			publisher
+				.publish(
+					&chat_id,
+					TypingEvent {
+						chatroom: chat_id,
+						user_id: payload.user_id,
+						typing: payload.typing,
+					},
+				)
+				.unwrap();
+		}
+
+		Ok(())
+	}
+	fn expire_typing(&mut self, publisher: &mut TypingPublisher) {
+		let now = Instant::now();
+
+		for (chat_id, chatroom) in &mut self.chatrooms {
+			chatroom.typing.retain(|user_id, expires_at| {
+				if *expires_at > now {
+					return true;
+				}
+
+				publisher
+					.publish(
+						chat_id,
+						TypingEvent {
+							chatroom: *chat_id,
+							user_id: *user_id,
+							typing: false,
+						},
+					)
+					.unwrap();
+
+				false
+			});
+		}
+	}
+	async fn on_subscribe(&mut self, topic: &Uuid) -> sqlx::Result<TypingContext> {
+		let chatroom_data = match self.chatrooms.entry(topic.clone()) {
+			Entry::Occupied(occupied_entry) => occupied_entry.into_mut(),
+			Entry::Vacant(vacant_entry) => {
+				self.db.listen(&channel_name_from_uuid(topic)).await?;
+
+				vacant_entry.insert(ChatroomState {
+					listeners_n: 0,
+					typing: HashMap::new(),
+				})
+			}
+		};
+		chatroom_data.listeners_n += 1;
+
+		Ok(TypingContext {
+			typing: chatroom_data.typing.keys().copied().collect(),
+		})
+	}
+	async fn on_unsubscribe(&mut self, topic: &Uuid) -> Result<(), sqlx::Error> {
+		let listeners_n = &mut self.chatrooms.get_mut(topic).unwrap().listeners_n;
+		*listeners_n -= 1;
+
+		if *listeners_n == 0 {
+			self.db.unlisten(&channel_name_from_uuid(topic)).await?;
+			self.chatrooms.remove(topic);
+		}
+
+		Ok(())
+	}
+}
+
+fn channel_name_from_uuid(uuid: &Uuid) -> String {
+	format!("typing-{uuid}")
+}
+fn uuid_from_channel_name(name: &str) -> Uuid {
+	name.strip_prefix("typing-").unwrap().parse().unwrap()
+}
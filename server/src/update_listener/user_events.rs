@@ -0,0 +1,194 @@
+use crate::database::{Database, chatroom_members::ChatroomRole};
+use ahash::{HashMap, HashMapExt};
+use anyhow::{Context, bail};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::{
+	PgPool,
+	postgres::{PgListener, PgNotification},
+};
+use std::{collections::hash_map::Entry, convert::Infallible};
+use tokio::{select, spawn};
+use tokio_pubsub::{EventReactor, Publisher, PublisherHandle};
+use tracing::error;
+use uuid::Uuid;
+
+pub struct UserEventsListener {
+	db: Database<PgListener>,
+	// number of listeners per user, will stop listening when it reaches 0
+	users: HashMap<Uuid, u32>,
+}
+
+type UserEventsPublisher = Publisher<Uuid, UserEvent, ()>;
+
+/// Events concerning only a single user, delivered to all of their connections
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum UserEvent {
+	ReadMarker {
+		chatroom: Uuid,
+		last_read_seq_id: i64,
+	},
+	/// The user became a member of a chatroom
+	ChatroomAdded {
+		chatroom: Uuid,
+		name: String,
+		archived: bool,
+		last_seq_id: Option<i64>,
+		/// the other user if this is a direct chat
+		direct_with: Option<Uuid>,
+	},
+	ChatroomUpdated {
+		chatroom: Uuid,
+		name: String,
+		archived: bool,
+	},
+	/// The user is no longer a member of a chatroom, or it was deleted
+	ChatroomRemoved { chatroom: Uuid },
+	/// Someone else became a member of a chatroom of the user
+	MemberAdded { chatroom: Uuid, user_id: Uuid },
+	/// Someone else is no longer a member of a chatroom of the user
+	MemberRemoved { chatroom: Uuid, user_id: Uuid },
+	/// The role or mute of the user in a chatroom changed
+	MembershipUpdated {
+		chatroom: Uuid,
+		role: ChatroomRole,
+		muted_until: Option<DateTime<Utc>>,
+	},
+	/// A session of the user was logged out, revoked or expired
+	SessionRevoked { session_id: Uuid },
+	/// Events might have been lost, everything they are about has to be checked again.
+	/// Never sent by the database
+	#[serde(skip_deserializing)]
+	Resync,
+	/// A kind that this version of the server doesn't handle, never published
+	#[serde(other)]
+	Unknown,
+}
+
+pub async fn start(db: &Database<PgPool>) -> sqlx::Result<PublisherHandle<Uuid, UserEvent, ()>> {
+	let publisher = Publisher::new();
+	let handle = publisher.handle();
+
+	let user_events_listener = UserEventsListener::new(db).await?;
+	spawn(async move {
+		if let Err(e) = user_events_listener.run(publisher).await {
+			error!("{e:?}");
+		}
+	});
+
+	Ok(handle)
+}
+
+impl UserEventsListener {
+	pub async fn new(db: &Database<PgPool>) -> sqlx::Result<Self> {
+		let listener = PgListener::connect_with(&db.inner).await?;
+
+		Ok(Self {
+			db: Database::new(listener),
+			users: HashMap::new(),
+		})
+	}
+	// publisher has to be separate from Self, because drive() borrows self, and we need self again to finish it
+	pub async fn run(mut self, mut publisher: UserEventsPublisher) -> anyhow::Result<()> {
+		loop {
+			select! {
+				driver = publisher.drive() => {
+					struct Reactor<'a>(&'a mut UserEventsListener);
+
+					impl<'a> EventReactor<Uuid, (), Infallible> for Reactor<'a> {
+						type Error = sqlx::Error;
+
+						async fn on_subscribe(
+							&mut self,
+							topic: &Uuid,
+						) -> Result<Result<(), Infallible>, Self::Error> {
+							self.0.on_subscribe(topic).await.map(Ok)
+						}
+						async fn on_unsubscribe(&mut self, topic: &Uuid) -> Result<(), Self::Error> {
+							self.0.on_unsubscribe(topic).await
+						}
+					}
+
+					driver.finish(Reactor(&mut self)).await?;
+				},
+				notification = self.db.try_recv() => {
+					self.handle_notification(&mut publisher, notification?).await.context("handle notification")?;
+				}
+			}
+		}
+	}
+	async fn handle_notification(
+		&mut self,
+		publisher: &mut UserEventsPublisher,
+		notification: Option<PgNotification>,
+	) -> anyhow::Result<()> {
+		let notification = match notification {
+			Some(x) => x,
+			// disrupted connection, events in the meantime are lost
+			None => {
+				for user_id in self.users.keys() {
+					publisher.publish(user_id, UserEvent::Resync).unwrap();
+				}
+
+				return Ok(());
+			}
+		};
+
+		let user_id: Uuid = uuid_from_channel_name(notification.channel());
+
+		// was already in flight when the last listener unsubscribed
+		if !self.users.contains_key(&user_id) {
+			return Ok(());
+		}
+
+		let event: UserEvent = match serde_json::from_str(notification.payload()) {
+			Ok(x) => x,
+			Err(e) => {
+				bail!(
+					"couldnt parse notification payload ({}): {e}",
+					notification.payload()
+				);
+			}
+		};
+
+		if let UserEvent::Unknown = event {
+			return Ok(());
+		}
+
+		publisher.publish(&user_id, event).unwrap();
+
+		Ok(())
+	}
+	async fn on_subscribe(&mut self, topic: &Uuid) -> sqlx::Result<()> {
+		let listeners_n = match self.users.entry(*topic) {
+			Entry::Occupied(occupied_entry) => occupied_entry.into_mut(),
+			Entry::Vacant(vacant_entry) => {
+				self.db.listen(&channel_name_from_uuid(topic)).await?;
+
+				vacant_entry.insert(0)
+			}
+		};
+		*listeners_n += 1;
+
+		Ok(())
+	}
+	async fn on_unsubscribe(&mut self, topic: &Uuid) -> Result<(), sqlx::Error> {
+		let listeners_n = self.users.get_mut(topic).unwrap();
+		*listeners_n -= 1;
+
+		if *listeners_n == 0 {
+			self.db.unlisten(&channel_name_from_uuid(topic)).await?;
+			self.users.remove(topic);
+		}
+
+		Ok(())
+	}
+}
+
+fn channel_name_from_uuid(uuid: &Uuid) -> String {
+	format!("user-{uuid}")
+}
+fn uuid_from_channel_name(name: &str) -> Uuid {
+	name.strip_prefix("user-").unwrap().parse().unwrap()
+}
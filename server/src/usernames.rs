@@ -0,0 +1,45 @@
+use crate::database::Database;
+use ahash::{HashMap, HashMapExt};
+use sqlx::PgPool;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+// the whole cache is cleared when it reaches this size
+const MAX_CACHED_USERNAMES: usize = 10_000;
+
+/// Resolves user ids to usernames, caching them.
+///
+/// Cloning it will point to the same cache.
+#[derive(Clone, Debug)]
+pub struct UsernameCache {
+	db: Database<PgPool>,
+	cache: Arc<Mutex<HashMap<Uuid, Arc<str>>>>,
+}
+
+impl UsernameCache {
+	pub fn new(db: &Database<PgPool>) -> Self {
+		Self {
+			db: db.clone(),
+			cache: Arc::new(Mutex::new(HashMap::new())),
+		}
+	}
+	/// Returns `None` if the user doesn't exist
+	pub async fn get(&mut self, user_id: Uuid) -> sqlx::Result<Option<Arc<str>>> {
+		if let Some(username) = self.cache.lock().unwrap().get(&user_id) {
+			return Ok(Some(Arc::clone(username)));
+		}
+
+		let username: Arc<str> = match self.db.user_by_id(user_id).await? {
+			Some(user) => user.username.into(),
+			None => return Ok(None),
+		};
+
+		let mut cache = self.cache.lock().unwrap();
+		if cache.len() >= MAX_CACHED_USERNAMES {
+			cache.clear();
+		}
+		cache.insert(user_id, Arc::clone(&username));
+
+		Ok(Some(username))
+	}
+}
@@ -0,0 +1,11 @@
+use server::endpoints::auth::hash_token;
+use uuid::Uuid;
+
+#[test]
+fn hash_of_token_bytes() {
+	// base64 of the SHA-256 of the 16 bytes of the token, not of its string form
+	assert_eq!(
+		hash_token(Uuid::from_u128(1)),
+		"fDzNELt+w3tG03kmrmJ0Jn8AejSurxXIgqcVp/MwBSk="
+	);
+}
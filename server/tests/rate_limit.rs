@@ -0,0 +1,70 @@
+use server::{
+	config::RateLimit,
+	rate_limit::{Action, Key, RateLimited, refill_interval},
+};
+use std::{net::IpAddr, time::Duration};
+use uuid::Uuid;
+
+#[test]
+fn bucket_keys() {
+	let ip: IpAddr = "127.0.0.1".parse().unwrap();
+	let user = Uuid::from_u128(1);
+
+	assert_eq!(
+		Key::Ip(ip).to_bucket_key(Action::Login),
+		"login:ip:127.0.0.1"
+	);
+	assert_eq!(
+		Key::User(user).to_bucket_key(Action::SendMessage),
+		"send_message:user:00000000-0000-0000-0000-000000000001"
+	);
+	assert_eq!(
+		Key::Email("a@b.c").to_bucket_key(Action::Email),
+		"email:email:a@b.c"
+	);
+}
+
+#[test]
+fn bucket_keys_per_action() {
+	let ip: IpAddr = "::1".parse().unwrap();
+
+	assert_ne!(
+		Key::Ip(ip).to_bucket_key(Action::Login),
+		Key::Ip(ip).to_bucket_key(Action::Email)
+	);
+}
+
+#[test]
+fn email_buckets_ignore_case() {
+	assert_eq!(
+		Key::Email("Alex@Email.com").to_bucket_key(Action::Login),
+		Key::Email("alex@email.com").to_bucket_key(Action::Login)
+	);
+}
+
+#[test]
+fn refill_intervals() {
+	let limit = |per_minute| RateLimit {
+		burst: 10,
+		per_minute,
+	};
+
+	assert_eq!(refill_interval(limit(1)), Duration::from_secs(60));
+	assert_eq!(refill_interval(limit(2)), Duration::from_secs(30));
+	assert_eq!(refill_interval(limit(60)), Duration::from_secs(1));
+	assert_eq!(refill_interval(limit(120)), Duration::from_millis(500));
+	// at least 1 per minute
+	assert_eq!(refill_interval(limit(0)), Duration::from_secs(60));
+}
+
+#[test]
+fn retry_after_rounds_up() {
+	let limited = |retry_after| RateLimited { retry_after };
+
+	assert_eq!(limited(Duration::from_secs(30)).retry_after_secs(), 30);
+	assert_eq!(
+		limited(Duration::from_millis(29_001)).retry_after_secs(),
+		30
+	);
+	assert_eq!(limited(Duration::from_millis(1)).retry_after_secs(), 1);
+}
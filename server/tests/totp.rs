@@ -0,0 +1,117 @@
+use server::totp::{
+	code_at, decrypt_secret, encode_secret, encrypt_secret, generate_recovery_codes, is_totp_code,
+	normalize_recovery_code, verify_at,
+};
+use uuid::Uuid;
+
+// the secret of the test vectors in RFC 4226 and RFC 6238
+const SECRET: &[u8] = b"12345678901234567890";
+
+#[test]
+fn rfc4226_hotp() {
+	let expected = [
+		755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871, 520489,
+	];
+
+	for (counter, code) in expected.into_iter().enumerate() {
+		assert_eq!(code_at(SECRET, counter as i64), code, "counter {counter}");
+	}
+}
+
+#[test]
+fn rfc6238_totp_sha1() {
+	// the last 6 of the 8 digits in the RFC
+	let expected = [
+		(59, 287082),
+		(1111111109, 81804),
+		(1111111111, 50471),
+		(1234567890, 5924),
+		(2000000000, 279037),
+		(20000000000, 353130),
+	];
+
+	for (time, code) in expected {
+		assert_eq!(code_at(SECRET, time / 30), code, "time {time}");
+	}
+}
+
+#[test]
+fn verify_with_drift() {
+	let now = 1234567890 / 30;
+
+	assert_eq!(verify_at(SECRET, "005924", now), Some(now));
+	assert_eq!(verify_at(SECRET, "005924", now - 1), Some(now));
+	assert_eq!(verify_at(SECRET, "005924", now + 1), Some(now));
+	assert_eq!(verify_at(SECRET, "005924", now + 2), None);
+	assert_eq!(verify_at(SECRET, "005924", now - 2), None);
+}
+
+#[test]
+fn verify_rejects_malformed_codes() {
+	let now = 1234567890 / 30;
+
+	// leading zeros are part of the code
+	assert_eq!(verify_at(SECRET, "5924", now), None);
+	assert_eq!(verify_at(SECRET, "0005924", now), None);
+	assert_eq!(verify_at(SECRET, "+05924", now), None);
+	assert_eq!(verify_at(SECRET, " 05924", now), None);
+	assert_eq!(verify_at(SECRET, "", now), None);
+}
+
+#[test]
+fn totp_code_shape() {
+	assert!(is_totp_code("123456"));
+	assert!(!is_totp_code("12345"));
+	assert!(!is_totp_code("1234567"));
+	assert!(!is_totp_code("12345a"));
+	assert!(!is_totp_code("abcde-fghij"));
+}
+
+#[test]
+fn secret_encoding() {
+	assert_eq!(encode_secret(SECRET), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+}
+
+#[test]
+fn secret_encryption() {
+	let key = [1; 32];
+	let user = Uuid::from_u128(1);
+
+	let encrypted = encrypt_secret(&key, user, SECRET);
+	assert_ne!(&encrypted[..], SECRET);
+	assert_eq!(
+		decrypt_secret(&key, user, &encrypted).as_deref(),
+		Some(SECRET)
+	);
+
+	// a new nonce every time
+	assert_ne!(encrypt_secret(&key, user, SECRET), encrypted);
+
+	assert_eq!(decrypt_secret(&[2; 32], user, &encrypted), None);
+	assert_eq!(decrypt_secret(&key, Uuid::from_u128(2), &encrypted), None);
+	assert_eq!(decrypt_secret(&key, user, SECRET), None);
+	assert_eq!(decrypt_secret(&key, user, &[]), None);
+}
+
+#[test]
+fn normalize_recovery_codes() {
+	assert_eq!(normalize_recovery_code("abcde-12345"), "abcde12345");
+	assert_eq!(normalize_recovery_code("ABCDE-12345"), "abcde12345");
+	assert_eq!(normalize_recovery_code(" abcde 12345 "), "abcde12345");
+	assert_eq!(normalize_recovery_code("abcde12345"), "abcde12345");
+	assert_eq!(normalize_recovery_code("abcdé-12345"), "abcd12345");
+}
+
+#[test]
+fn generated_recovery_codes() {
+	let codes = generate_recovery_codes();
+
+	assert_eq!(codes.len(), 10);
+	for code in &codes {
+		assert_eq!(code.len(), 11);
+		assert_eq!(code.as_bytes()[5], b'-');
+		assert!(!is_totp_code(code));
+		// only the separator is dropped when they are hashed
+		assert_eq!(normalize_recovery_code(code), code.replace('-', ""));
+	}
+}